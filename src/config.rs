@@ -0,0 +1,362 @@
+//! 配置文件（porg.toml，TOML 格式）
+//!
+//! 依次读取用户配置 `$XDG_CONFIG_HOME/porg/porg.toml` 与源目录下的 `porg.toml`，后者覆盖前者；
+//! 用 `--config` 指定时只读取该文件。键名为命令行长选项名（`-` 也可写作 `_`），
+//! `[profile.名称]` 中的设置在 `--profile 名称` 时覆盖顶层设置，命令行中指定的选项优先于配置文件。
+//!
+//! ```toml
+//! output = "/mnt/nas/Photos"
+//! format = "{date:%Y}/{date:%m-%d}"
+//! jobs = 8
+//!
+//! [time_shift]
+//! "Canon EOS 5D" = "+01:00:30"
+//! "0123456789" = "-00:05:00"
+//!
+//! [profile.sdcard]
+//! source = "/media/sdcard/DCIM"
+//! move = true
+//! ```
+
+use crate::timeshift::ShiftRule;
+use crate::toml::{self, Table, Value};
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 配置文件名
+pub const FILE_NAME: &str = "porg.toml";
+
+/// 不能写在配置文件中的选项（参数 ID）
+const RESERVED_KEYS: &[&str] = &["help", "version", "config", "profile", "print_config"];
+
+/// 已读取的配置文件
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// 读取的文件（按覆盖顺序）
+    pub paths: Vec<PathBuf>,
+    table: Table,
+}
+
+impl Config {
+    /// 只读取指定的文件
+    pub fn load(path: &Path) -> Result<Config> {
+        Ok(Config {
+            paths: vec![path.to_path_buf()],
+            table: read(path)?,
+        })
+    }
+
+    /// 读取用户配置与源目录中的项目配置（都不存在时为空）
+    pub fn discover(source_dir: &Path) -> Result<Config> {
+        let user_dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        let candidates = user_dir
+            .map(|dir| dir.join("porg").join(FILE_NAME))
+            .into_iter()
+            .chain([source_dir.join(FILE_NAME)]);
+
+        let mut config = Config::default();
+        for path in candidates.filter(|path| path.is_file()) {
+            merge(&mut config.table, read(&path)?);
+            config.paths.push(path);
+        }
+        Ok(config)
+    }
+
+    /// 读取到的文件列表，用于提示
+    pub fn describe_paths(&self) -> String {
+        let paths: Vec<String> = self.paths.iter().map(|p| p.display().to_string()).collect();
+        paths.join(", ")
+    }
+
+    /// 生效的设置：顶层设置叠加 `[profile.名称]`
+    pub fn settings(&self, profile: Option<&str>) -> Result<Table> {
+        let mut settings = self.table.clone();
+        let profiles = match settings.remove("profile") {
+            Some(Value::Table(profiles)) => profiles,
+            Some(other) => anyhow::bail!("profile 应为表，实际为{}", other.type_name()),
+            None => Table::new(),
+        };
+        let Some(name) = profile else {
+            return Ok(settings);
+        };
+        if self.paths.is_empty() {
+            anyhow::bail!("没有找到配置文件，无法使用 --profile {}", name);
+        }
+        match profiles.get(name) {
+            Some(Value::Table(overrides)) => merge(&mut settings, overrides.clone()),
+            Some(other) => anyhow::bail!("profile.{} 应为表，实际为{}", name, other.type_name()),
+            None => {
+                let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                anyhow::bail!(
+                    "配置文件中没有 [profile.{}]（可用: {}）",
+                    name,
+                    if names.is_empty() { "无".to_string() } else { names.join(", ") }
+                );
+            }
+        }
+        Ok(settings)
+    }
+
+    /// 顶层的相机时钟校正规则（`porg fix-dates` 使用）
+    pub fn time_shift(&self) -> Result<Vec<ShiftRule>> {
+        let Some(value) = self.table.get("time_shift") else {
+            return Ok(Vec::new());
+        };
+        let mut rules = Vec::new();
+        for rule in time_shift_values(value)? {
+            rules.push(rule.parse().map_err(|e: String| anyhow::anyhow!(e))?);
+        }
+        Ok(rules)
+    }
+}
+
+/// 读取并解析单个配置文件
+fn read(path: &Path) -> Result<Table> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("无法读取配置文件: {}", path.display()))?;
+    toml::parse(&text).with_context(|| format!("配置文件格式错误: {}", path.display()))
+}
+
+/// 把 `overlay` 合并进 `base`：同名的表逐键合并，其余值直接覆盖
+fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// `time_shift` 的两种写法：`{ 相机 = "偏移" }` 表或 `["相机=偏移", ...]` 数组
+fn time_shift_values(value: &Value) -> Result<Vec<String>> {
+    match value {
+        Value::Table(rules) => rules
+            .iter()
+            .map(|(camera, shift)| match shift {
+                Value::String(shift) => Ok(format!("{}={}", camera, shift)),
+                _ => anyhow::bail!("time_shift.\"{}\" 应为字符串，如 \"+01:00:30\"", camera),
+            })
+            .collect(),
+        other => scalar_values("time_shift", other),
+    }
+}
+
+/// 标量或数组中的全部值
+fn scalar_values(key: &str, value: &Value) -> Result<Vec<String>> {
+    match value {
+        Value::Array(items) => items.iter().map(|item| scalar(key, item)).collect(),
+        other => Ok(vec![scalar(key, other)?]),
+    }
+}
+
+fn scalar(key: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(n) => Ok(n.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        other => anyhow::bail!("`{}` 应为字符串或数字，实际为{}", key, other.type_name()),
+    }
+}
+
+/// 配置项对应的命令行参数
+fn find_arg<'c>(cmd: &'c Command, key: &str) -> Option<&'c Arg> {
+    let long = key.replace('_', "-");
+    cmd.get_arguments().find(|arg| match arg.get_long() {
+        Some(name) => name == long,
+        None => arg.is_positional() && arg.get_id() == key,
+    })
+}
+
+/// 把配置项转换为放在命令行参数之前的参数，返回参数及来自配置文件的选项 ID。
+/// 命令行中已指定的选项（以及与之冲突的选项）保持命令行的值
+pub fn to_args(
+    settings: &Table,
+    cmd: &Command,
+    matches: &ArgMatches,
+) -> Result<(Vec<OsString>, HashSet<String>)> {
+    let given: Vec<&Arg> = cmd
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect();
+
+    let mut args = Vec::new();
+    let mut from_config = HashSet::new();
+    for (key, value) in settings {
+        let arg = find_arg(cmd, key)
+            .filter(|arg| !RESERVED_KEYS.contains(&arg.get_id().as_str()))
+            .with_context(|| format!("未知的配置项 `{}`", key))?;
+        let id = arg.get_id().as_str();
+        // 时钟校正规则与命令行中的规则合并（排在前面，同级时被命令行覆盖）
+        let overridden = id != "time_shift"
+            && given.iter().any(|g| {
+                g.get_id() == arg.get_id()
+                    || cmd.get_arg_conflicts_with(g).iter().any(|c| c.get_id() == arg.get_id())
+                    || cmd.get_arg_conflicts_with(arg).iter().any(|c| c.get_id() == g.get_id())
+            });
+        if overridden {
+            continue;
+        }
+
+        let values = match (arg.get_action(), value) {
+            (ArgAction::SetTrue, Value::Boolean(true)) => {
+                args.push(OsString::from(format!("--{}", arg.get_long().unwrap_or(id))));
+                from_config.insert(id.to_string());
+                continue;
+            }
+            (ArgAction::SetTrue, Value::Boolean(false)) => continue,
+            (ArgAction::SetTrue, other) => {
+                anyhow::bail!("`{}` 应为布尔值，实际为{}", key, other.type_name())
+            }
+            (ArgAction::Append, value) if id == "time_shift" => time_shift_values(value)?,
+            (ArgAction::Append, value) => scalar_values(key, value)?,
+            (_, Value::Array(_)) => anyhow::bail!("`{}` 只能有一个值，不能为数组", key),
+            (_, value) => vec![scalar(key, value)?],
+        };
+        for value in values {
+            // 使用 --key=value 形式，值以 `-` 开头（如 -00:05）时也不会被当作选项
+            args.push(OsString::from(match arg.get_long() {
+                Some(long) => format!("--{}={}", long, value),
+                None => value,
+            }));
+        }
+        from_config.insert(id.to_string());
+    }
+    Ok((args, from_config))
+}
+
+/// 以 TOML 格式列出生效的全部选项，注释中标明来源
+/// （`user` 为只含命令行参数的解析结果，`matches` 为合并配置文件后的结果）
+pub fn render_effective(
+    cmd: &Command,
+    user: &ArgMatches,
+    matches: &ArgMatches,
+    from_config: &HashSet<String>,
+) -> String {
+    let mut out = String::from("# 生效的配置（优先级: 命令行 > 配置文件 > 默认值）\n");
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        if RESERVED_KEYS.contains(&id) {
+            continue;
+        }
+        let Some(raw) = matches.get_raw(id) else {
+            continue;
+        };
+        let values: Vec<String> = raw.map(|v| v.to_string_lossy().to_string()).collect();
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => values.join(""),
+            ArgAction::Append => {
+                let items: Vec<String> = values.iter().map(|v| literal(v)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            _ => values.first().map(|v| literal(v)).unwrap_or_default(),
+        };
+        let from_cli = user.value_source(id) == Some(ValueSource::CommandLine);
+        let origin = match (from_config.contains(id), from_cli) {
+            (true, true) => "配置文件 + 命令行",
+            (true, false) => "配置文件",
+            (false, true) => "命令行",
+            (false, false) => "默认值",
+        };
+        let key = arg.get_long().unwrap_or(id).replace('-', "_");
+        let line = format!("{} = {}", key, value);
+        out.push_str(&format!("{:<48} # {}\n", line, origin));
+    }
+    out
+}
+
+/// 整数原样输出，其余作为 TOML 基本字符串
+fn literal(value: &str) -> String {
+    if value.parse::<i64>().is_ok() {
+        return value.to_string();
+    }
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::value_parser;
+
+    fn command() -> Command {
+        Command::new("porg")
+            .arg(Arg::new("source").default_value("."))
+            .arg(Arg::new("format").long("format").short('f').default_value("%Y-%m-%d"))
+            .arg(Arg::new("jobs").long("jobs").value_parser(value_parser!(usize)))
+            .arg(Arg::new("mode").long("mode").default_value("copy"))
+            .arg(
+                Arg::new("move")
+                    .long("move")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("mode"),
+            )
+            .arg(Arg::new("time_shift").long("time-shift").action(ArgAction::Append))
+    }
+
+    #[test]
+    fn profile_overrides_top_level_and_cli_overrides_both() {
+        let config = Config {
+            paths: vec![PathBuf::from(FILE_NAME)],
+            table: toml::parse(
+                r#"
+format = "%Y"
+jobs = 4
+[time_shift]
+Canon = "-00:05"
+[profile.sdcard]
+source = "/media/sd"
+move = true
+"#,
+            )
+            .unwrap(),
+        };
+        let settings = config.settings(Some("sdcard")).unwrap();
+        assert!(config.settings(Some("missing")).is_err());
+
+        let user = ["porg", "-f", "%m", "--mode", "copy"];
+        let matches = command().get_matches_from(user);
+        let (args, from_config) = to_args(&settings, &command(), &matches).unwrap();
+        // format 与 move（与 --mode 冲突）由命令行决定
+        assert_eq!(args, ["--jobs=4", "/media/sd", "--time-shift=Canon=-00:05"]);
+        assert!(from_config.contains("jobs") && !from_config.contains("format"));
+
+        let argv = ["porg".into()].into_iter().chain(args).chain(user[1..].iter().map(Into::into));
+        let matches = command().get_matches_from(argv);
+        assert_eq!(matches.get_one::<String>("format").unwrap(), "%m");
+        assert_eq!(matches.get_one::<String>("source").unwrap(), "/media/sd");
+        let user = command().get_matches_from(user);
+        let effective = render_effective(&command(), &user, &matches, &from_config);
+        assert!(effective.contains("time_shift = [\"Canon=-00:05\"]"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_wrong_types() {
+        let matches = command().get_matches_from(["porg"]);
+        let check = |text: &str| to_args(&toml::parse(text).unwrap(), &command(), &matches);
+        assert!(check("colour = true").is_err());
+        assert!(check("move = \"yes\"").is_err());
+        assert!(check("format = [\"a\", \"b\"]").is_err());
+        assert!(check("config = \"x\"").is_err());
+    }
+}
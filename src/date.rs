@@ -0,0 +1,435 @@
+//! 拍摄时间：从 EXIF / 视频元数据提取，并按时区选项确定归档日期
+
+use crate::filename::FilenameDateRecognizer;
+use crate::video;
+use anyhow::{Context, Result};
+use chrono::{
+    DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Timelike,
+};
+use clap::ValueEnum;
+use exif::{In, Reader, Tag};
+use std::fmt;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// EXIF 日期时间的常见格式
+const EXIF_DATE_FORMATS: &[&str] = &[
+    "%Y:%m:%d %H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y:%m:%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+];
+
+/// 拍摄时间
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureDate {
+    /// 已知 UTC 偏移（EXIF OffsetTime*、QuickTime 元数据）
+    Zoned(DateTime<FixedOffset>),
+    /// 未记录时区，视为拍摄地本地时间
+    Naive(NaiveDateTime),
+}
+
+impl CaptureDate {
+    /// 拍摄地本地时间
+    pub fn local(&self) -> NaiveDateTime {
+        match self {
+            CaptureDate::Zoned(dt) => dt.naive_local(),
+            CaptureDate::Naive(dt) => *dt,
+        }
+    }
+
+    /// 按相机时钟偏差校正后的时间；超出可表示的日期范围时报错
+    pub fn shifted(&self, delta: TimeDelta) -> Result<CaptureDate> {
+        let shifted = match self {
+            CaptureDate::Zoned(dt) => dt.checked_add_signed(delta).map(CaptureDate::Zoned),
+            CaptureDate::Naive(dt) => dt.checked_add_signed(delta).map(CaptureDate::Naive),
+        };
+        shifted.with_context(|| {
+            format!("时间 {} 偏移 {} 秒后超出日期范围", self.local(), delta.num_seconds())
+        })
+    }
+
+    pub fn offset(&self) -> Option<FixedOffset> {
+        match self {
+            CaptureDate::Zoned(dt) => Some(*dt.offset()),
+            CaptureDate::Naive(_) => None,
+        }
+    }
+
+    /// 按时区选项换算后的时间；未记录时区或按拍摄地时间归档时为 None
+    fn converted(&self, tz: &TzMode) -> Option<DateTime<FixedOffset>> {
+        match (self, tz) {
+            (CaptureDate::Naive(_), _) | (_, TzMode::Capture) => None,
+            (CaptureDate::Zoned(dt), TzMode::System) => {
+                Some(dt.with_timezone(&Local).fixed_offset())
+            }
+            (CaptureDate::Zoned(dt), TzMode::Fixed(offset)) => Some(dt.with_timezone(offset)),
+        }
+    }
+
+    /// 按时区选项得到用于归档的时间；未记录时区的时间无法换算，保持不变
+    pub fn filing_time(&self, tz: &TzMode) -> NaiveDateTime {
+        self.converted(tz)
+            .map(|dt| dt.naive_local())
+            .unwrap_or_else(|| self.local())
+    }
+
+    /// 单个文件输出行中的日期说明，如 `2023-06-15 10:15:00 +08:00`
+    pub fn describe(&self, tz: &TzMode) -> String {
+        let mut text = self.local().format("%Y-%m-%d %H:%M:%S").to_string();
+        if let Some(offset) = self.offset() {
+            text.push_str(&format!(" {}", offset));
+        }
+        if let Some(dt) = self.converted(tz).filter(|dt| self.offset() != Some(*dt.offset())) {
+            text.push_str(&format!(" → {}", dt.format("%Y-%m-%d %H:%M:%S %:z")));
+        }
+        text
+    }
+}
+
+/// 归档时区
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TzMode {
+    /// 按拍摄地本地时间归档
+    Capture,
+    /// 换算为本机时区
+    System,
+    /// 换算为指定的固定偏移
+    Fixed(FixedOffset),
+}
+
+impl FromStr for TzMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "capture" => Ok(TzMode::Capture),
+            "system" | "local" => Ok(TzMode::System),
+            "utc" | "z" => Ok(TzMode::Fixed(FixedOffset::east_opt(0).unwrap())),
+            _ => parse_offset(s).map(TzMode::Fixed).ok_or_else(|| {
+                format!("无效的时区: {}（可选 capture、system、utc 或 ±HH:MM）", s)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for TzMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TzMode::Capture => f.write_str("拍摄地时间"),
+            TzMode::System => f.write_str("本机时区"),
+            TzMode::Fixed(offset) => write!(f, "UTC{}", offset),
+        }
+    }
+}
+
+/// 日期来源
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum)]
+pub enum DateSource {
+    /// EXIF（视频为 QuickTime 元数据）
+    Exif,
+    /// 文件名
+    Filename,
+    /// 文件创建时间（statx birth time）
+    Btime,
+    /// 文件修改时间
+    Mtime,
+}
+
+impl fmt::Display for DateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DateSource::Exif => "EXIF",
+            DateSource::Filename => "文件名",
+            DateSource::Btime => "创建时间",
+            DateSource::Mtime => "修改时间",
+        })
+    }
+}
+
+/// 拍摄日期提取器：按 `--fallback` 指定的来源顺序依次尝试
+pub struct DateExtractor {
+    sources: Vec<DateSource>,
+    filename: FilenameDateRecognizer,
+}
+
+impl DateExtractor {
+    /// `filename_patterns` 为用户自定义的文件名正则，优先于内置模式
+    pub fn new(sources: &[DateSource], filename_patterns: &[String]) -> Result<Self> {
+        let mut unique = Vec::new();
+        for source in sources {
+            if !unique.contains(source) {
+                unique.push(*source);
+            }
+        }
+        Ok(DateExtractor {
+            sources: unique,
+            filename: FilenameDateRecognizer::new(filename_patterns)?,
+        })
+    }
+
+    /// 确定一组文件的拍摄日期及其来源：每个来源先在所有成员上尝试，再换下一个来源
+    pub fn extract(&self, paths: &[&Path]) -> Result<Option<(CaptureDate, DateSource)>> {
+        for &source in &self.sources {
+            for path in paths {
+                let date = match source {
+                    DateSource::Exif => extract_capture_date(path)?,
+                    DateSource::Filename => self.filename.recognize(path),
+                    DateSource::Btime => fs::metadata(path)
+                        .and_then(|m| m.created())
+                        .ok()
+                        .map(system_time_to_date),
+                    DateSource::Mtime => fs::metadata(path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .map(system_time_to_date),
+                };
+                if let Some(date) = date {
+                    return Ok(Some((date, source)));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// 文件系统时间戳换算为本机时区的时间
+fn system_time_to_date(time: SystemTime) -> CaptureDate {
+    CaptureDate::Zoned(DateTime::<Local>::from(time).fixed_offset())
+}
+
+/// 从 EXIF 元信息（视频则从 QuickTime 元数据）提取拍照日期
+pub fn extract_capture_date(path: &Path) -> Result<Option<CaptureDate>> {
+    if video::is_video(path) {
+        return video::extract_creation_date(path);
+    }
+
+    let file = fs::File::open(path).context("无法打开文件")?;
+    let mut buf_reader = BufReader::new(file);
+
+    let exif = match Reader::new().read_from_container(&mut buf_reader) {
+        Ok(exif) => exif,
+        Err(_) => return Ok(None),
+    };
+
+    let ascii = |tag: Tag| {
+        exif.get_field(tag, In::PRIMARY)
+            .map(|field| field.display_value().to_string())
+    };
+
+    // 按优先级尝试不同的日期字段，各自配合对应的时区偏移（缺失时退回 OffsetTime）与亚秒
+    let date_tags = [
+        (Tag::DateTimeOriginal, Tag::OffsetTimeOriginal, Tag::SubSecTimeOriginal),
+        (Tag::DateTimeDigitized, Tag::OffsetTimeDigitized, Tag::SubSecTimeDigitized),
+        (Tag::DateTime, Tag::OffsetTime, Tag::SubSecTime),
+    ];
+
+    for (date_tag, offset_tag, subsec_tag) in date_tags {
+        let Some(mut dt) = ascii(date_tag).and_then(|s| parse_exif_date(&s)) else {
+            continue;
+        };
+        if let Some(nanos) = ascii(subsec_tag).and_then(|s| parse_subsec(&s)) {
+            dt = dt.with_nanosecond(nanos).unwrap_or(dt);
+        }
+        let offset = ascii(offset_tag)
+            .or_else(|| ascii(Tag::OffsetTime))
+            .and_then(|s| parse_offset(s.trim().trim_matches('"')));
+        return Ok(Some(with_offset(dt, offset)));
+    }
+
+    Ok(None)
+}
+
+/// 组合本地时间与可选的 UTC 偏移
+pub fn with_offset(dt: NaiveDateTime, offset: Option<FixedOffset>) -> CaptureDate {
+    match offset.and_then(|o| o.from_local_datetime(&dt).single()) {
+        Some(zoned) => CaptureDate::Zoned(zoned),
+        None => CaptureDate::Naive(dt),
+    }
+}
+
+/// 解析手动指定的日期，如 `2023-06-15 10:15`、`2023-06-15T10:15:00+09:00`
+pub fn parse_manual_date(s: &str) -> Result<CaptureDate, String> {
+    let s = s.trim();
+    let invalid = || format!("无效的日期: {}（格式: YYYY-MM-DD HH:MM[:SS][±HH:MM]）", s);
+    let parse = |text: &str| {
+        parse_exif_date(text).or_else(|| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M")
+                .ok()
+                .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))
+        })
+    };
+    if let Some(dt) = parse(s) {
+        return Ok(CaptureDate::Naive(dt));
+    }
+    // 末尾带时区偏移：+09:00、+0900 或 Z
+    for len in [6, 5, 1] {
+        let Some(split) = s.len().checked_sub(len).filter(|&i| s.is_char_boundary(i)) else {
+            continue;
+        };
+        let (text, offset) = s.split_at(split);
+        if let (Some(dt), Some(offset)) = (parse(text.trim_end()), parse_offset(offset)) {
+            return Ok(with_offset(dt, Some(offset)));
+        }
+    }
+    Err(invalid())
+}
+
+/// 尝试多种格式解析 EXIF 日期字符串
+fn parse_exif_date(date_str: &str) -> Option<NaiveDateTime> {
+    let trimmed = date_str.trim().trim_matches('"');
+    for fmt in EXIF_DATE_FORMATS {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(trimmed, fmt) {
+            return Some(dt);
+        }
+    }
+    None
+}
+
+/// 解析 EXIF SubSecTime（秒的小数部分，如 "123" 表示 0.123 秒）为纳秒
+fn parse_subsec(s: &str) -> Option<u32> {
+    let digits = s.trim().trim_matches('"').trim();
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let padded = format!("{:0<9}", &digits[..digits.len().min(9)]);
+    padded.parse().ok()
+}
+
+/// 解析 `+08:00`、`-0530`、`Z` 形式的 UTC 偏移
+pub fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("z") {
+        return FixedOffset::east_opt(0);
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = s[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offset(hours: i32) -> FixedOffset {
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    fn naive(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_offset("+08:00"), Some(offset(8)));
+        assert_eq!(parse_offset(" -0530 "), FixedOffset::west_opt(5 * 3600 + 30 * 60));
+        assert_eq!(parse_offset("Z"), Some(offset(0)));
+        assert_eq!(parse_offset("z"), Some(offset(0)));
+        for invalid in ["", "08:00", "+8:00", "+24:00", "+08:60", "+08:00:00", "+0８:00", "+"] {
+            assert_eq!(parse_offset(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn files_across_midnight_by_tz_mode() {
+        // 东京时间 1 月 1 日 01:00，即 UTC 12 月 31 日 16:00
+        let date = with_offset(naive("2024-01-01 01:00:00"), Some(offset(9)));
+        assert_eq!(date.filing_time(&TzMode::Capture), naive("2024-01-01 01:00:00"));
+        assert_eq!(date.filing_time(&"utc".parse().unwrap()), naive("2023-12-31 16:00:00"));
+        assert_eq!(date.filing_time(&TzMode::Fixed(offset(-8))), naive("2023-12-31 08:00:00"));
+        assert_eq!(date.local(), naive("2024-01-01 01:00:00"));
+
+        // 未记录时区时无法换算
+        let floating = CaptureDate::Naive(naive("2024-01-01 01:00:00"));
+        assert_eq!(floating.filing_time(&TzMode::Fixed(offset(-8))), floating.local());
+        assert_eq!(floating.offset(), None);
+    }
+
+    #[test]
+    fn shifts_dates() {
+        let date = with_offset(naive("2024-01-01 01:00:00"), Some(offset(9)));
+        let shifted = date.shifted(TimeDelta::hours(-2)).unwrap();
+        assert_eq!(shifted.local(), naive("2023-12-31 23:00:00"));
+        assert_eq!(shifted.offset(), Some(offset(9)));
+        let floating = CaptureDate::Naive(naive("2024-01-01 01:00:00"));
+        let shifted = floating.shifted(TimeDelta::seconds(30)).unwrap();
+        assert_eq!(shifted.local(), naive("2024-01-01 01:00:30"));
+
+        let huge = TimeDelta::seconds(i64::MAX / 1000);
+        assert!(date.shifted(huge).is_err());
+        assert!(floating.shifted(-huge).is_err());
+    }
+
+    #[test]
+    fn describes_dates() {
+        let date = with_offset(naive("2024-01-01 01:00:00"), Some(offset(9)));
+        assert_eq!(date.describe(&TzMode::Capture), "2024-01-01 01:00:00 +09:00");
+        assert_eq!(
+            date.describe(&TzMode::Fixed(offset(0))),
+            "2024-01-01 01:00:00 +09:00 → 2023-12-31 16:00:00 +00:00"
+        );
+        // 目标时区与拍摄时区相同时不重复显示
+        assert_eq!(date.describe(&TzMode::Fixed(offset(9))), "2024-01-01 01:00:00 +09:00");
+        let floating = CaptureDate::Naive(naive("2024-01-01 01:00:00"));
+        assert_eq!(floating.describe(&TzMode::Fixed(offset(0))), "2024-01-01 01:00:00");
+    }
+
+    #[test]
+    fn parses_tz_modes() {
+        assert_eq!("capture".parse(), Ok(TzMode::Capture));
+        assert_eq!("LOCAL".parse(), Ok(TzMode::System));
+        assert_eq!("+05:30".parse(), Ok(TzMode::Fixed(FixedOffset::east_opt(19800).unwrap())));
+        assert!("Asia/Tokyo".parse::<TzMode>().is_err());
+    }
+
+    #[test]
+    fn parses_subseconds() {
+        assert_eq!(parse_subsec("5"), Some(500_000_000));
+        assert_eq!(parse_subsec(" 123 "), Some(123_000_000));
+        assert_eq!(parse_subsec("\"042\""), Some(42_000_000));
+        assert_eq!(parse_subsec("1234567891"), Some(123_456_789));
+        assert_eq!(parse_subsec(""), None);
+        assert_eq!(parse_subsec("12a"), None);
+        assert_eq!(parse_subsec("-1"), None);
+    }
+
+    #[test]
+    fn parses_manual_dates() {
+        let expected = CaptureDate::Naive(naive("2023-06-15 10:15:00"));
+        assert_eq!(parse_manual_date("2023-06-15 10:15"), Ok(expected));
+        assert_eq!(parse_manual_date(" 2023:06:15 10:15:00 "), Ok(expected));
+        assert_eq!(parse_manual_date("2023-06-15T10:15:00"), Ok(expected));
+        assert_eq!(
+            parse_manual_date("2023-06-15"),
+            Ok(CaptureDate::Naive(naive("2023-06-15 00:00:00")))
+        );
+
+        let zoned = with_offset(naive("2023-06-15 10:15:00"), Some(offset(9)));
+        assert_eq!(parse_manual_date("2023-06-15T10:15:00+09:00"), Ok(zoned));
+        assert_eq!(parse_manual_date("2023-06-15 10:15 +0900"), Ok(zoned));
+        assert_eq!(
+            parse_manual_date("2023-06-15 10:15:00Z"),
+            Ok(with_offset(naive("2023-06-15 10:15:00"), Some(offset(0))))
+        );
+
+        for invalid in ["", "2023-13-01", "yesterday", "2023-06-15 10:15 +25:00", "日期2023"] {
+            assert!(parse_manual_date(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! 按内容检测重复文件：先按文件大小筛选，大小相同时再比较 SHA-256
+
+use crate::hash::{hash_file, Digest};
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 已知文件（摘要按需计算）
+struct Entry {
+    path: PathBuf,
+    /// 读取内容所用的路径：尚未写入的目标文件以其源文件代替
+    content: PathBuf,
+    digest: Option<Digest>,
+}
+
+/// 查重结果
+pub struct Lookup {
+    pub size: u64,
+    /// 仅在存在同大小的候选文件时才会计算
+    pub digest: Option<Digest>,
+    /// 内容相同的已有文件
+    pub duplicate_of: Option<PathBuf>,
+}
+
+/// 本次运行及目标目录中已知文件的索引
+#[derive(Default)]
+pub struct DedupIndex {
+    by_size: HashMap<u64, Vec<Entry>>,
+    scanned_dirs: HashSet<PathBuf>,
+}
+
+impl DedupIndex {
+    /// 将目标目录中已有的文件加入索引（每个目录只扫描一次）
+    pub fn scan_dir(&mut self, dir: &Path) {
+        if !self.scanned_dirs.insert(dir.to_path_buf()) {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            // 跟随符号链接（--mode symlink 的输出）
+            let Ok(meta) = fs::metadata(entry.path()) else {
+                continue;
+            };
+            if meta.is_file() {
+                self.insert(entry.path(), entry.path(), meta.len(), None);
+            }
+        }
+    }
+
+    /// 查找与给定文件内容相同的已知文件
+    pub fn lookup(&mut self, path: &Path) -> Result<Lookup> {
+        let size = fs::metadata(path)
+            .with_context(|| format!("无法读取文件信息: {}", path.display()))?
+            .len();
+
+        let Some(candidates) = self.by_size.get_mut(&size) else {
+            return Ok(Lookup {
+                size,
+                digest: None,
+                duplicate_of: None,
+            });
+        };
+
+        let digest = hash_file(path)?;
+        let mut duplicate_of = None;
+        for entry in candidates.iter_mut() {
+            if entry.path == path || entry.content == path {
+                continue;
+            }
+            if entry.digest.is_none() {
+                // 候选文件不可读时视为不同
+                entry.digest = hash_file(&entry.content).ok();
+            }
+            if entry.digest == Some(digest) {
+                duplicate_of = Some(entry.path.clone());
+                break;
+            }
+        }
+
+        Ok(Lookup {
+            size,
+            digest: Some(digest),
+            duplicate_of,
+        })
+    }
+
+    /// 登记一个已处理（或计划写入 `path`、内容来自 `content`）的文件
+    pub fn insert(&mut self, path: PathBuf, content: PathBuf, size: u64, digest: Option<Digest>) {
+        self.by_size.entry(size).or_default().push(Entry {
+            path,
+            content,
+            digest,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_duplicates_by_content() {
+        let dir = std::env::temp_dir().join(format!("porg-dedup-{}", std::process::id()));
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("existing.jpg"), b"photo-1").unwrap();
+        fs::write(dir.join("copy.jpg"), b"photo-1").unwrap();
+        fs::write(dir.join("other.jpg"), b"photo-2").unwrap();
+        fs::write(dir.join("new.jpg"), b"new photo").unwrap();
+
+        let mut index = DedupIndex::default();
+        index.scan_dir(&out);
+        let copy = index.lookup(&dir.join("copy.jpg")).unwrap();
+        assert_eq!(copy.duplicate_of, Some(out.join("existing.jpg")));
+        // 大小相同但内容不同
+        let other = index.lookup(&dir.join("other.jpg")).unwrap();
+        assert_eq!(other.duplicate_of, None);
+        assert!(other.digest.is_some());
+        // 没有同大小的候选文件时不计算摘要
+        let new = index.lookup(&dir.join("new.jpg")).unwrap();
+        assert_eq!((new.duplicate_of, new.digest), (None, None));
+
+        // 尚未写入的目标按源文件内容比较，查找源文件本身不算重复
+        let planned = out.join("new.jpg");
+        index.insert(planned.clone(), dir.join("new.jpg"), new.size, None);
+        assert_eq!(index.lookup(&dir.join("new.jpg")).unwrap().duplicate_of, None);
+        fs::write(dir.join("new-copy.jpg"), b"new photo").unwrap();
+        assert_eq!(index.lookup(&dir.join("new-copy.jpg")).unwrap().duplicate_of, Some(planned));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+//! 按时间间隔把照片聚类为事件（`--group-by event`）
+
+use chrono::{NaiveDateTime, TimeDelta};
+use clap::ValueEnum;
+use std::collections::HashMap;
+
+/// 目录分组方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// 每张照片按自己的拍摄时间套用目录模板
+    Day,
+    /// 相邻照片间隔不超过阈值的归为同一事件，按事件开始时间套用目录模板
+    Event,
+}
+
+/// 事件目录名后附加的标签
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventLabel {
+    City,
+    Region,
+    Country,
+}
+
+/// 一个事件
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// 事件中最早的拍摄时间
+    pub start: NaiveDateTime,
+}
+
+/// 按时间排序后，在相邻间隔超过 `gap` 处切分事件
+///
+/// 返回事件列表以及每个输入所属的事件下标（无日期的输入为 `None`）。
+pub fn cluster(
+    times: &[Option<NaiveDateTime>],
+    gap: TimeDelta,
+) -> (Vec<Event>, Vec<Option<usize>>) {
+    let mut order: Vec<(NaiveDateTime, usize)> = times
+        .iter()
+        .enumerate()
+        .filter_map(|(i, t)| t.map(|t| (t, i)))
+        .collect();
+    order.sort();
+
+    let mut events: Vec<Event> = Vec::new();
+    let mut membership = vec![None; times.len()];
+    let mut previous: Option<NaiveDateTime> = None;
+    for (time, i) in order {
+        if previous.is_none_or(|p| time - p > gap) {
+            events.push(Event { start: time });
+        }
+        membership[i] = Some(events.len() - 1);
+        previous = Some(time);
+    }
+    (events, membership)
+}
+
+/// 取出现次数最多的值，次数相同时取最先出现的
+pub fn most_common<'a>(values: impl Iterator<Item = &'a str>) -> Option<String> {
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (order, value) in values.enumerate() {
+        counts.entry(value).or_insert((0, order)).0 += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1 .0.cmp(&b.1 .0).then(b.1 .1.cmp(&a.1 .1)))
+        .map(|(value, _)| value.to_string())
+}
+
+/// 解析时长，如 `4h`、`90m`、`1h30m`、`2d`、`45s`
+pub fn parse_duration(s: &str) -> Result<TimeDelta, String> {
+    let mut total = TimeDelta::zero();
+    let mut number = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: i64 = number
+            .parse()
+            .map_err(|_| format!("无效的时长: {}（示例: 4h、90m、1h30m）", s))?;
+        number.clear();
+        let part = match c.to_ascii_lowercase() {
+            'd' => TimeDelta::try_days(value),
+            'h' => TimeDelta::try_hours(value),
+            'm' => TimeDelta::try_minutes(value),
+            's' => TimeDelta::try_seconds(value),
+            _ => return Err(format!("无效的时长单位 `{}`: {}（可用 d、h、m、s）", c, s)),
+        };
+        total = part
+            .and_then(|part| total.checked_add(&part))
+            .ok_or_else(|| format!("时长过长: {}", s))?;
+    }
+    if !number.is_empty() {
+        return Err(format!("时长缺少单位: {}（示例: 4h、90m、1h30m）", s));
+    }
+    if total <= TimeDelta::zero() {
+        return Err(format!("时长必须大于 0: {}", s));
+    }
+    Ok(total)
+}
+
+/// 以 `1h30m` 形式显示时长
+pub fn format_duration(d: TimeDelta) -> String {
+    let secs = d.num_seconds();
+    let parts = [
+        (secs / 86400, "d"),
+        (secs % 86400 / 3600, "h"),
+        (secs % 3600 / 60, "m"),
+        (secs % 60, "s"),
+    ];
+    parts
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> Option<NaiveDateTime> {
+        Some(NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    #[test]
+    fn splits_on_gaps_across_midnight() {
+        let times = [
+            at("2023-06-15 22:30"),
+            at("2023-06-15 09:00"),
+            None,
+            at("2023-06-16 00:45"),
+            at("2023-06-15 19:00"),
+            at("2023-06-15 10:30"),
+        ];
+        let (events, membership) = cluster(&times, TimeDelta::hours(4));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].start, at("2023-06-15 09:00").unwrap());
+        assert_eq!(events[1].start, at("2023-06-15 19:00").unwrap());
+        assert_eq!(membership, [Some(1), Some(0), None, Some(1), Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("4h"), Ok(TimeDelta::hours(4)));
+        assert_eq!(parse_duration("1h30m"), Ok(TimeDelta::minutes(90)));
+        assert!(parse_duration("4").is_err());
+        assert!(parse_duration("4x").is_err());
+        assert!(parse_duration("0h").is_err());
+        assert!(parse_duration("99999999999999999d").is_err());
+        assert!(parse_duration("106751991167d106751991167d").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert_eq!(format_duration(TimeDelta::minutes(90)), "1h30m");
+    }
+}
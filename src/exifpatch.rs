@@ -0,0 +1,395 @@
+//! 写回拍摄日期到 EXIF
+//!
+//! 只做两种不会破坏文件结构的修改：
+//! - 原地覆盖已存在的 DateTimeOriginal / OffsetTimeOriginal（长度固定，偏移不变），
+//!   同时覆盖已存在的 DateTimeDigitized、DateTime 及其时区字段，保持各日期一致；
+//!   没有 OffsetTimeOriginal 字段时只写日期，时区由调用方另存；
+//! - 为没有 EXIF 的 JPEG 插入只含日期与时区字段的新 APP1 段。
+//!
+//! 其余情况（需要新增字段、非 JPEG/TIFF 格式）由调用方改写 XMP 附属文件。
+
+use crate::preserve::{self, Preserve};
+use crate::transfer;
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, NaiveDateTime};
+use exif::experimental::Writer;
+use exif::{Field, In, Tag, Value};
+use std::fs;
+use std::io::{Cursor, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// 可以原地写入 EXIF 的扩展名
+const WRITABLE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "tif", "tiff", "dng"];
+
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+const TAG_OFFSET_TIME: u16 = 0x9010;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_OFFSET_TIME_DIGITIZED: u16 = 0x9012;
+const TYPE_ASCII: u16 = 2;
+
+/// 日期字段的长度（`YYYY:MM:DD HH:MM:SS` 与结尾的 NUL）
+const DATE_LEN: usize = 20;
+/// 时区字段的长度（`±HH:MM` 与结尾的 NUL）
+const OFFSET_LEN: usize = 7;
+
+/// 写入结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Patched {
+    /// 覆盖了已有字段
+    InPlace,
+    /// 覆盖了日期，但文件中没有 OffsetTimeOriginal 字段可写时区
+    DateOnly,
+    /// 插入了新的 EXIF 段
+    NewExif,
+}
+
+/// 是否为可以写入 EXIF 的格式
+pub fn is_writable(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| WRITABLE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// 写入拍摄日期；无法安全写入时返回 `Ok(None)`
+pub fn write_date(
+    path: &Path,
+    time: NaiveDateTime,
+    offset: Option<FixedOffset>,
+    dry_run: bool,
+) -> Result<Option<Patched>> {
+    let mut data = fs::read(path).with_context(|| format!("无法读取: {}", path.display()))?;
+    let is_jpeg = data.starts_with(&[0xFF, 0xD8]);
+
+    let tiff_start = if is_jpeg {
+        match find_exif_segment(&data) {
+            Some(start) => start,
+            None => {
+                let Some(position) = app1_insert_position(&data) else {
+                    return Ok(None);
+                };
+                let segment = new_exif_segment(time, offset)?;
+                data.splice(position..position, segment);
+                if !dry_run {
+                    replace_file(path, &data)?;
+                }
+                return Ok(Some(Patched::NewExif));
+            }
+        }
+    } else {
+        0
+    };
+
+    let Some(fields) = find_date_fields(&data, tiff_start) else {
+        return Ok(None);
+    };
+    if fields.dates[0].is_none() {
+        return Ok(None);
+    }
+
+    let date_text = format!("{}\0", time.format("%Y:%m:%d %H:%M:%S"));
+    if date_text.len() != DATE_LEN {
+        return Ok(None);
+    }
+    let mut patches: Vec<(usize, &[u8])> = Vec::new();
+    patches.extend(fields.dates.iter().flatten().map(|&pos| (pos, date_text.as_bytes())));
+    let offset_text = offset.map(|offset| format!("{}\0", offset_string(offset)));
+    if let Some(text) = &offset_text {
+        patches.extend(fields.offsets.iter().flatten().map(|&pos| (pos, text.as_bytes())));
+    }
+    if !dry_run {
+        patch_file(path, &patches)?;
+    }
+    match (offset, fields.offsets[0]) {
+        (Some(_), None) => Ok(Some(Patched::DateOnly)),
+        _ => Ok(Some(Patched::InPlace)),
+    }
+}
+
+/// `+09:00` 形式的时区
+fn offset_string(offset: FixedOffset) -> String {
+    DateTime::UNIX_EPOCH.with_timezone(&offset).format("%:z").to_string()
+}
+
+/// 原地覆盖文件中的字节（长度不变），保留文件本身及其权限、所有者、扩展属性、硬链接与修改时间
+fn patch_file(path: &Path, patches: &[(usize, &[u8])]) -> Result<()> {
+    let meta = fs::metadata(path).with_context(|| format!("无法读取: {}", path.display()))?;
+    let mut file = fs::File::options()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("无法写入: {}", path.display()))?;
+    for &(pos, bytes) in patches {
+        file.seek(SeekFrom::Start(pos as u64))
+            .and_then(|_| file.write_all(bytes))
+            .with_context(|| format!("无法写入: {}", path.display()))?;
+    }
+    let mut times = fs::FileTimes::new();
+    if let Ok(modified) = meta.modified() {
+        times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = meta.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    let _ = file.set_times(times);
+    Ok(())
+}
+
+/// 原子地替换文件内容，并保留原文件的修改时间、权限与扩展属性
+fn replace_file(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = transfer::temp_path(path);
+    let result = fs::write(&tmp, data)
+        .and_then(|()| preserve::apply(path, &tmp, Preserve::all()))
+        .with_context(|| format!("无法写入: {}", tmp.display()))
+        .and_then(|_| {
+            fs::rename(&tmp, path).with_context(|| format!("无法替换: {}", path.display()))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// 日期字段值所在的文件偏移，依次为 Original、Digitized 与 IFD0 的 DateTime
+struct DateFields {
+    dates: [Option<usize>; 3],
+    /// 对应的 OffsetTimeOriginal、OffsetTimeDigitized 与 OffsetTime
+    offsets: [Option<usize>; 3],
+}
+
+/// 查找 JPEG 中 EXIF APP1 段内 TIFF 头的位置
+fn find_exif_segment(data: &[u8]) -> Option<usize> {
+    segments(data).find_map(|(marker, start, end)| {
+        (marker == 0xE1 && data[start..end].starts_with(b"Exif\0\0")).then_some(start + 6)
+    })
+}
+
+/// 新 APP1 段的插入位置：SOI 之后，跳过 JFIF APP0
+fn app1_insert_position(data: &[u8]) -> Option<usize> {
+    if data.len() < 4 {
+        return None;
+    }
+    match segments(data).next() {
+        Some((0xE0, _, end)) => Some(end),
+        Some(_) => Some(2),
+        None => None,
+    }
+}
+
+/// 遍历 JPEG 图像数据之前的标记段：(标记, 段数据起点, 段数据终点)
+fn segments(data: &[u8]) -> impl Iterator<Item = (u8, usize, usize)> + '_ {
+    let mut pos = 2;
+    std::iter::from_fn(move || {
+        if pos + 4 > data.len() || data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        // SOS 之后是压缩数据
+        if marker == 0xDA {
+            return None;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let start = pos + 4;
+        let end = pos + 2 + len;
+        if len < 2 || end > data.len() {
+            return None;
+        }
+        pos = end;
+        Some((marker, start, end))
+    })
+}
+
+/// 在 TIFF 结构中查找各日期与时区字段的值位置
+fn find_date_fields(data: &[u8], tiff: usize) -> Option<DateFields> {
+    let header = data.get(tiff..tiff + 8)?;
+    let little = match &header[..4] {
+        b"II*\0" => true,
+        b"MM\0*" => false,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let b = data.get(pos..pos + 2)?;
+        let b = [b[0], b[1]];
+        Some(if little { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let b: [u8; 4] = data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if little { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    };
+    // IFD 条目: (标签, 类型, 数量, 值或偏移所在位置)
+    let entries = |ifd: usize| -> Option<Vec<(u16, u16, u32, usize)>> {
+        let count = u16_at(tiff + ifd)? as usize;
+        (0..count)
+            .map(|i| {
+                let entry = tiff + ifd + 2 + i * 12;
+                Some((u16_at(entry)?, u16_at(entry + 2)?, u32_at(entry + 4)?, entry + 8))
+            })
+            .collect()
+    };
+
+    let ifd0 = u32_at(tiff + 4)? as usize;
+    let ifd0_entries = entries(ifd0)?;
+    let exif_ifd = ifd0_entries
+        .iter()
+        .find(|&&(tag, _, _, _)| tag == TAG_EXIF_IFD)
+        .and_then(|&(_, _, _, value)| u32_at(value))? as usize;
+
+    // 长度超过 4 字节的 ASCII 值存放在偏移处
+    let ascii_value = |entry: &(u16, u16, u32, usize), len: usize| -> Option<usize> {
+        let &(_, kind, count, value) = entry;
+        if kind != TYPE_ASCII || count as usize != len {
+            return None;
+        }
+        let pos = tiff.checked_add(u32_at(value)? as usize)?;
+        (pos.checked_add(len)? <= data.len()).then_some(pos)
+    };
+    let exif_entries = entries(exif_ifd)?;
+    let find = |entries: &[(u16, u16, u32, usize)], tag: u16, len: usize| {
+        entries
+            .iter()
+            .find(|entry| entry.0 == tag)
+            .and_then(|entry| ascii_value(entry, len))
+    };
+    let exif = |tag: u16, len: usize| find(&exif_entries, tag, len);
+    Some(DateFields {
+        dates: [
+            exif(TAG_DATE_TIME_ORIGINAL, DATE_LEN),
+            exif(TAG_DATE_TIME_DIGITIZED, DATE_LEN),
+            find(&ifd0_entries, TAG_DATE_TIME, DATE_LEN),
+        ],
+        offsets: [
+            exif(TAG_OFFSET_TIME_ORIGINAL, OFFSET_LEN),
+            exif(TAG_OFFSET_TIME_DIGITIZED, OFFSET_LEN),
+            exif(TAG_OFFSET_TIME, OFFSET_LEN),
+        ],
+    })
+}
+
+/// 只含日期与时区字段的 EXIF APP1 段
+fn new_exif_segment(time: NaiveDateTime, offset: Option<FixedOffset>) -> Result<Vec<u8>> {
+    let ascii = |tag: Tag, text: String| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![text.into_bytes()]),
+    };
+    let date = time.format("%Y:%m:%d %H:%M:%S").to_string();
+    let mut fields = vec![
+        ascii(Tag::DateTime, date.clone()),
+        ascii(Tag::DateTimeOriginal, date.clone()),
+        ascii(Tag::DateTimeDigitized, date),
+    ];
+    if let Some(offset) = offset {
+        let text = offset_string(offset);
+        fields.push(ascii(Tag::OffsetTime, text.clone()));
+        fields.push(ascii(Tag::OffsetTimeOriginal, text.clone()));
+        fields.push(ascii(Tag::OffsetTimeDigitized, text));
+    }
+
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = Cursor::new(Vec::new());
+    writer.write(&mut tiff, false).context("无法生成 EXIF 数据")?;
+    let tiff = tiff.into_inner();
+
+    let len = u16::try_from(2 + 6 + tiff.len()).context("EXIF 数据过长")?;
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&len.to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(&tiff);
+    Ok(segment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::Reader;
+
+    fn read_field(jpeg: &[u8], tag: Tag) -> String {
+        let exif = Reader::new()
+            .read_from_container(&mut Cursor::new(jpeg))
+            .unwrap();
+        let field = exif.get_field(tag, In::PRIMARY).unwrap();
+        field.display_value().to_string()
+    }
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn new_segment_round_trips_and_can_be_patched() {
+        let offset = FixedOffset::east_opt(9 * 3600);
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend(new_exif_segment(time("2023-06-15 10:15:00"), offset).unwrap());
+        jpeg.extend([0xFF, 0xD9]);
+        for tag in [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime] {
+            assert_eq!(read_field(&jpeg, tag), "2023-06-15 10:15:00");
+        }
+
+        let tiff = find_exif_segment(&jpeg).unwrap();
+        let fields = find_date_fields(&jpeg, tiff).unwrap();
+        assert!(fields.dates.iter().chain(&fields.offsets).all(Option::is_some));
+        let pos = fields.dates[0].unwrap();
+        jpeg[pos..pos + 19].copy_from_slice(b"2024:01:02 03:04:05");
+        assert_eq!(read_field(&jpeg, Tag::DateTimeOriginal), "2024-01-02 03:04:05");
+        let pos = fields.offsets[0].unwrap();
+        assert_eq!(&jpeg[pos..pos + OFFSET_LEN], b"+09:00\0");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn patches_all_dates_in_place() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("porg-exifpatch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.jpg");
+        let link = dir.join("b.jpg");
+        let mut jpeg = vec![0xFF, 0xD8];
+        let utc = FixedOffset::east_opt(0);
+        jpeg.extend(new_exif_segment(time("2023-06-15 10:15:00"), utc).unwrap());
+        jpeg.extend([0xFF, 0xD9]);
+        fs::write(&path, &jpeg).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        fs::hard_link(&path, &link).unwrap();
+
+        let offset = FixedOffset::west_opt(5 * 3600 + 30 * 60);
+        let patched = write_date(&path, time("2024-01-02 03:04:05"), offset, false).unwrap();
+        assert_eq!(patched, Some(Patched::InPlace));
+
+        // 硬链接指向同一文件，权限不变
+        let data = fs::read(&link).unwrap();
+        assert_eq!(data.len(), jpeg.len());
+        for tag in [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime] {
+            assert_eq!(read_field(&data, tag), "2024-01-02 03:04:05");
+        }
+        for tag in [Tag::OffsetTimeOriginal, Tag::OffsetTimeDigitized, Tag::OffsetTime] {
+            assert_eq!(read_field(&data, tag), "\"-05:30\"");
+        }
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+
+        // 没有 EXIF 的 JPEG 插入新段，保留权限
+        let bare = dir.join("c.jpg");
+        let mut jfif = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        jfif.extend(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        jfif.extend([0xFF, 0xD9]);
+        fs::write(&bare, &jfif).unwrap();
+        fs::set_permissions(&bare, fs::Permissions::from_mode(0o600)).unwrap();
+        let patched = write_date(&bare, time("2024-01-02 03:04:05"), None, false).unwrap();
+        assert_eq!(patched, Some(Patched::NewExif));
+        assert_eq!(read_field(&fs::read(&bare).unwrap(), Tag::DateTime), "2024-01-02 03:04:05");
+        assert_eq!(fs::metadata(&bare).unwrap().permissions().mode() & 0o777, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn formats_offsets() {
+        assert_eq!(offset_string(FixedOffset::east_opt(9 * 3600).unwrap()), "+09:00");
+        assert_eq!(offset_string(FixedOffset::west_opt(5 * 3600 + 30 * 60).unwrap()), "-05:30");
+        assert_eq!(offset_string(FixedOffset::east_opt(0).unwrap()), "+00:00");
+    }
+}
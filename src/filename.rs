@@ -0,0 +1,164 @@
+//! 从文件名推断拍摄日期（EXIF 缺失时的后备来源）
+//!
+//! 模式使用命名分组：`year` `month` `day` `hour` `minute` `second`，
+//! 或 Unix 时间戳 `ts`（秒）/ `ts_ms`（毫秒）。
+
+use crate::date::CaptureDate;
+use crate::pattern::{PatternError, Regex};
+use chrono::{DateTime, Local, NaiveDate};
+use std::path::Path;
+
+/// 内置文件名模式（按顺序尝试）
+const BUILTIN_PATTERNS: &[&str] = &[
+    // WhatsApp: IMG-20230615-WA0001.jpg
+    r"(?i)^(?:IMG|VID)-(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})-WA\d+",
+    // 微信: mmexport1686820000000.jpg、wx_camera_1686820000000.jpg
+    r"(?i)^(?:mmexport|wx_camera_)(?P<ts_ms>\d{13})",
+    // macOS 截图: Screenshot 2023-06-15 at 10.15.00.png
+    r"(?i)^Screen ?shot[ _](?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})[ _](?:at[ _])?(?P<hour>\d{1,2})\.(?P<minute>\d{2})\.(?P<second>\d{2})",
+    // Android 相机/截图/Pixel: IMG_20230615_101500.jpg、Screenshot_20230615-101500.png、PXL_20230615_101500123.jpg
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})(?P<month>\d{2})(?P<day>\d{2})[_-]?(?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+    // 2023-06-15 10.15.00 / 2023-06-15_10-15-00
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})-(?P<month>\d{2})-(?P<day>\d{2})[ _T-](?P<hour>\d{2})[.:-](?P<minute>\d{2})[.:-](?P<second>\d{2})",
+    // 仅日期: 2023-06-15、20230615
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})-?(?P<month>\d{2})-?(?P<day>\d{2})(?:\D|$)",
+];
+
+/// 文件名日期识别器：先尝试用户模式，再尝试内置模式
+pub struct FilenameDateRecognizer {
+    patterns: Vec<Regex>,
+}
+
+impl FilenameDateRecognizer {
+    /// 编译用户模式与内置模式
+    pub fn new(user_patterns: &[String]) -> Result<Self, PatternError> {
+        let mut patterns = Vec::new();
+        for p in user_patterns {
+            let regex = Regex::new(p)?;
+            if !has_date_groups(&regex) {
+                return Err(PatternError {
+                    pattern: p.clone(),
+                    message: "需要命名分组 year/month/day 或 ts/ts_ms".to_string(),
+                });
+            }
+            patterns.push(regex);
+        }
+        for p in BUILTIN_PATTERNS {
+            patterns.push(Regex::new(p)?);
+        }
+        Ok(FilenameDateRecognizer { patterns })
+    }
+
+    /// 从文件名（不含扩展名）识别日期
+    pub fn recognize(&self, path: &Path) -> Option<CaptureDate> {
+        let stem = path.file_stem()?.to_str()?;
+        self.patterns.iter().find_map(|p| match_date(p, stem))
+    }
+}
+
+fn has_date_groups(regex: &Regex) -> bool {
+    let has = |name: &str| regex.has_group(name);
+    (has("year") && has("month") && has("day")) || has("ts") || has("ts_ms")
+}
+
+/// 用单个模式匹配并校验日期
+fn match_date(regex: &Regex, text: &str) -> Option<CaptureDate> {
+    let caps = regex.captures(text)?;
+
+    let timestamp = |name: &str, scale: i64| {
+        let value: i64 = caps.name(name)?.parse().ok()?;
+        let secs = value.div_euclid(scale);
+        let nanos = (value.rem_euclid(scale) * (1_000_000_000 / scale)) as u32;
+        DateTime::from_timestamp(secs, nanos)
+    };
+    if let Some(utc) = timestamp("ts_ms", 1000).or_else(|| timestamp("ts", 1)) {
+        // 时间戳为 UTC，换算为本机时区
+        return plausible_year(utc.naive_utc().date())
+            .then(|| CaptureDate::Zoned(utc.with_timezone(&Local).fixed_offset()));
+    }
+
+    let num = |name: &str| caps.name(name).and_then(|v| v.parse::<u32>().ok());
+    let date = NaiveDate::from_ymd_opt(num("year")? as i32, num("month")?, num("day")?)?;
+    if !plausible_year(date) {
+        return None;
+    }
+    let time = match num("hour") {
+        Some(hour) => {
+            date.and_hms_opt(hour, num("minute").unwrap_or(0), num("second").unwrap_or(0))?
+        }
+        None => date.and_hms_opt(0, 0, 0)?,
+    };
+    Some(CaptureDate::Naive(time))
+}
+
+/// 排除明显不是拍摄日期的数字串
+fn plausible_year(date: NaiveDate) -> bool {
+    use chrono::Datelike;
+    let next_year = Local::now().year() + 1;
+    (1990..=next_year).contains(&date.year())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recognize(name: &str) -> Option<String> {
+        let recognizer = FilenameDateRecognizer::new(&[]).unwrap();
+        let date = recognizer.recognize(Path::new(name))?;
+        Some(date.local().format("%Y-%m-%d %H:%M:%S").to_string())
+    }
+
+    #[test]
+    fn builtin_patterns() {
+        assert_eq!(recognize("IMG-20230615-WA0001.jpg").as_deref(), Some("2023-06-15 00:00:00"));
+        assert_eq!(
+            recognize("Screenshot 2023-06-15 at 9.05.07.png").as_deref(),
+            Some("2023-06-15 09:05:07")
+        );
+        assert_eq!(
+            recognize("PXL_20230615_101500123.MP.jpg").as_deref(),
+            Some("2023-06-15 10:15:00")
+        );
+        assert_eq!(
+            recognize("Screenshot_20230615-101500_Chrome.png").as_deref(),
+            Some("2023-06-15 10:15:00")
+        );
+        assert_eq!(recognize("2023-06-15_10-15-00.jpg").as_deref(), Some("2023-06-15 10:15:00"));
+        assert_eq!(recognize("trip 2023-06-15.jpg").as_deref(), Some("2023-06-15 00:00:00"));
+        assert_eq!(recognize("DSC_1234.jpg"), None);
+        // 不存在的日期与不合理的年份
+        assert_eq!(recognize("IMG_20230230_101500.jpg"), None);
+        assert_eq!(recognize("IMG_19800615_101500.jpg"), None);
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        let recognizer = FilenameDateRecognizer::new(&[]).unwrap();
+        let date = recognizer.recognize(Path::new("mmexport1686824100123.jpg")).unwrap();
+        let CaptureDate::Zoned(dt) = date else {
+            panic!("时间戳应带时区: {:?}", date);
+        };
+        assert_eq!(dt.timestamp_millis(), 1_686_824_100_123);
+        // 13 位以外的数字不是毫秒时间戳
+        assert_eq!(recognize("mmexport16868241001.jpg"), None);
+    }
+
+    #[test]
+    fn user_patterns_come_first() {
+        let patterns = [r"^shot-(?P<day>\d\d)\.(?P<month>\d\d)\.(?P<year>\d{4})".to_string()];
+        let recognizer = FilenameDateRecognizer::new(&patterns).unwrap();
+        let date = recognizer.recognize(Path::new("shot-15.06.2023-20220101.jpg")).unwrap();
+        assert_eq!(date.local().to_string(), "2023-06-15 00:00:00");
+
+        let seconds = [r"^t(?P<ts>\d+)$".to_string()];
+        let recognizer = FilenameDateRecognizer::new(&seconds).unwrap();
+        let CaptureDate::Zoned(dt) = recognizer.recognize(Path::new("t1686824100.jpg")).unwrap()
+        else {
+            panic!("时间戳应带时区");
+        };
+        assert_eq!(dt.timestamp(), 1_686_824_100);
+
+        assert!(FilenameDateRecognizer::new(&[r"(?P<year>\d{4})".to_string()]).is_err());
+        assert!(FilenameDateRecognizer::new(&["(".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! 扫描时的文件筛选：`--include` / `--exclude` 通配符、`.porgignore` 与源目录内的输出目录
+//!
+//! 通配符采用 gitignore 语法：`*` `?` `[a-z]` 不跨越 `/`，`**` 跨越任意层目录；
+//! 不含 `/` 的模式匹配任意层级的文件或目录名，含 `/` 的模式相对于源目录（或 `.porgignore`
+//! 所在目录）匹配；以 `/` 结尾的模式只匹配目录，`.porgignore` 中以 `!` 开头的规则重新包含。
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 每个目录中的忽略规则文件名
+pub const IGNORE_FILE: &str = ".porgignore";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`：不跨越 `/`
+    Star,
+    /// 结尾的 `**`：匹配其后的一切
+    Globstar,
+    /// `**/`：匹配零或多层目录
+    GlobstarSlash,
+    /// `[...]`
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+/// 一条通配符规则
+#[derive(Debug, Clone)]
+pub struct Glob {
+    tokens: Vec<Token>,
+    /// 只匹配目录（以 `/` 结尾）
+    dir_only: bool,
+    /// 重新包含（`.porgignore` 中以 `!` 开头）
+    negated: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, String> {
+        let (negated, pattern) = match pattern.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() {
+            return Err("通配符不能为空".to_string());
+        }
+
+        // 开头或中间含 `/` 时相对于基准目录匹配，否则匹配任意层级
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        let mut tokens = if anchored { Vec::new() } else { vec![Token::GlobstarSlash] };
+
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' => {
+                    i += 1;
+                    let c = *chars.get(i).ok_or_else(|| format!("通配符 `{}` 以 `\\` 结尾", pattern))?;
+                    tokens.push(Token::Char(c));
+                }
+                '?' => tokens.push(Token::Any),
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    let at_start = i == 0 || chars[i - 1] == '/';
+                    i += 1;
+                    match chars.get(i + 1) {
+                        Some('/') if at_start => {
+                            i += 1;
+                            tokens.push(Token::GlobstarSlash);
+                        }
+                        None if at_start => tokens.push(Token::Globstar),
+                        // 其他位置的连续星号等同于单个 `*`
+                        _ => tokens.push(Token::Star),
+                    }
+                }
+                '*' => tokens.push(Token::Star),
+                '[' => {
+                    let close = (i + 2..chars.len())
+                        .find(|&j| chars[j] == ']')
+                        .ok_or_else(|| format!("通配符 `{}` 中的 `[` 没有闭合", pattern))?;
+                    let mut body = &chars[i + 1..close];
+                    let negated = matches!(body.first(), Some('!' | '^'));
+                    if negated {
+                        body = &body[1..];
+                    }
+                    let mut ranges = Vec::new();
+                    let mut j = 0;
+                    while j < body.len() {
+                        if j + 2 < body.len() && body[j + 1] == '-' {
+                            ranges.push((body[j], body[j + 2]));
+                            j += 3;
+                        } else {
+                            ranges.push((body[j], body[j]));
+                            j += 1;
+                        }
+                    }
+                    tokens.push(Token::Class { negated, ranges });
+                    i = close;
+                }
+                c => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+        Ok(Glob {
+            tokens,
+            dir_only,
+            negated,
+        })
+    }
+
+    /// 匹配以 `/` 分隔的相对路径
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let chars: Vec<char> = path.chars().collect();
+        match_tokens(&self.tokens, &chars)
+    }
+}
+
+fn match_tokens(tokens: &[Token], s: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return s.is_empty();
+    };
+    match token {
+        Token::Char(c) => s.first() == Some(c) && match_tokens(rest, &s[1..]),
+        Token::Any => s.first().is_some_and(|&c| c != '/') && match_tokens(rest, &s[1..]),
+        Token::Class { negated, ranges } => s.first().is_some_and(|&c| {
+            let inside = ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+            c != '/' && inside != *negated && match_tokens(rest, &s[1..])
+        }),
+        Token::Star => {
+            for i in 0..=s.len() {
+                if match_tokens(rest, &s[i..]) {
+                    return true;
+                }
+                if s.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Token::Globstar => (0..=s.len()).any(|i| match_tokens(rest, &s[i..])),
+        Token::GlobstarSlash => {
+            match_tokens(rest, s)
+                || (1..=s.len()).any(|i| s[i - 1] == '/' && match_tokens(rest, &s[i..]))
+        }
+    }
+}
+
+/// 一个目录中的 `.porgignore`
+struct IgnoreFile {
+    rules: Vec<Glob>,
+}
+
+impl IgnoreFile {
+    /// 读取目录中的忽略规则（不存在时为 `None`）
+    fn load(dir: &Path) -> Option<IgnoreFile> {
+        let path = dir.join(IGNORE_FILE);
+        let text = fs::read_to_string(&path).ok()?;
+        let mut rules = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // `\#`、`\!` 表示字面量
+            let line = line.strip_prefix('\\').unwrap_or(line);
+            match Glob::new(line) {
+                Ok(glob) => rules.push(glob),
+                Err(e) => eprintln!("⚠️  忽略无效规则: {}:{} — {}", path.display(), index + 1, e),
+            }
+        }
+        Some(IgnoreFile { rules })
+    }
+
+    /// 最后一条匹配的规则决定结果：`Some(true)` 忽略，`Some(false)` 重新包含
+    fn ignores(&self, path: &str, is_dir: bool) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .map(|rule| !rule.negated)
+    }
+}
+
+/// 扫描源目录时使用的筛选条件
+pub struct Filter {
+    root: PathBuf,
+    includes: Vec<Glob>,
+    excludes: Vec<Glob>,
+    /// 不扫描的目录（如位于源目录内的输出目录）
+    skip_dirs: Vec<PathBuf>,
+    /// 目录 → 其中的 `.porgignore`
+    ignore_files: HashMap<PathBuf, Option<IgnoreFile>>,
+}
+
+impl Filter {
+    pub fn new(root: &Path, includes: &[String], excludes: &[String]) -> Result<Filter> {
+        let compile = |patterns: &[String], option: &str| -> Result<Vec<Glob>> {
+            patterns
+                .iter()
+                .map(|p| {
+                    let glob = Glob::new(p).map_err(|e| anyhow::anyhow!("{}: {}", option, e))?;
+                    if glob.negated {
+                        anyhow::bail!("{}: 不支持 `!` 开头的通配符 `{}`", option, p);
+                    }
+                    Ok(glob)
+                })
+                .collect()
+        };
+        Ok(Filter {
+            root: root.to_path_buf(),
+            includes: compile(includes, "--include")?,
+            excludes: compile(excludes, "--exclude")?,
+            skip_dirs: Vec::new(),
+            ignore_files: HashMap::new(),
+        })
+    }
+
+    /// 扫描时跳过该目录（目录不在源目录内时没有效果）
+    pub fn skip_dir(&mut self, dir: PathBuf) {
+        self.skip_dirs.push(dir);
+    }
+
+    /// 是否扫描该文件或目录（`--exclude`、`.porgignore` 与跳过的目录）
+    pub fn allows(&mut self, path: &Path, is_dir: bool) -> bool {
+        if is_dir && self.skip_dirs.iter().any(|dir| dir == path) {
+            return false;
+        }
+        let Some(rel) = relative(&self.root, path) else {
+            return true;
+        };
+        if self.excludes.iter().any(|glob| glob.matches(&rel, is_dir)) {
+            return false;
+        }
+
+        // 从源目录到父目录逐层应用 .porgignore，深层目录中的规则优先
+        let mut ignored = false;
+        for dir in path.ancestors().skip(1) {
+            if !dir.starts_with(&self.root) {
+                break;
+            }
+            let file = self
+                .ignore_files
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| IgnoreFile::load(dir));
+            let rel = relative(dir, path).unwrap_or_default();
+            if let Some(result) = file.as_ref().and_then(|f| f.ignores(&rel, is_dir)) {
+                ignored = result;
+                break;
+            }
+        }
+        !ignored
+    }
+
+    /// 媒体文件是否符合 `--include`（未指定时全部符合）
+    pub fn includes(&self, path: &Path) -> bool {
+        if self.includes.is_empty() {
+            return true;
+        }
+        let rel = relative(&self.root, path).unwrap_or_default();
+        self.includes.iter().any(|glob| glob.matches(&rel, false))
+    }
+}
+
+/// 以 `/` 分隔的相对路径；`path` 就是 `base` 时为 `None`
+fn relative(base: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(base).ok()?;
+    if rel.as_os_str().is_empty() {
+        return None;
+    }
+    let parts: Vec<String> = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().matches(path, false)
+    }
+
+    #[test]
+    fn gitignore_style_globs() {
+        assert!(matches("*.png", "a/b/screenshot.png"));
+        assert!(!matches("*.png", "a/b.png/c.jpg"));
+        assert!(matches("/raw/*.cr2", "raw/IMG_1.cr2"));
+        assert!(!matches("/raw/*.cr2", "x/raw/IMG_1.cr2"));
+        assert!(matches("2023/**/*.jpg", "2023/06/15/a.jpg"));
+        assert!(matches("2023/**/*.jpg", "2023/a.jpg"));
+        assert!(matches("trip/**", "trip/x/y.jpg"));
+        assert!(matches("IMG_[0-9][0-9]?.jpg", "IMG_12a.jpg"));
+        assert!(!matches("IMG_[!0-9]*", "IMG_1.jpg"));
+        assert!(!Glob::new("tmp/").unwrap().matches("tmp", false));
+        assert!(Glob::new("tmp/").unwrap().matches("x/tmp", true));
+        assert!(Glob::new("[a").is_err());
+    }
+
+    #[test]
+    fn nested_ignore_files_and_output_dir() {
+        let root = std::env::temp_dir().join(format!("porg-filter-{}", std::process::id()));
+        let sub = root.join("album");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(root.join(IGNORE_FILE), "*.png\nprivate/\n").unwrap();
+        fs::write(sub.join(IGNORE_FILE), "# 例外\n!keep.png\n").unwrap();
+
+        let mut filter = Filter::new(&root, &["*.jpg".to_string()], &["tmp".to_string()]).unwrap();
+        filter.skip_dir(root.join("organized"));
+        assert!(!filter.allows(&root.join("a.png"), false));
+        assert!(filter.allows(&sub.join("keep.png"), false));
+        assert!(!filter.allows(&sub.join("other.png"), false));
+        assert!(!filter.allows(&sub.join("private"), true));
+        assert!(!filter.allows(&root.join("organized"), true));
+        assert!(!filter.allows(&sub.join("tmp"), true));
+        assert!(filter.includes(&sub.join("a.jpg")) && !filter.includes(&sub.join("a.heic")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+//! `porg fix-dates`：把文件名推断、时钟校正或手动指定的日期写回文件
+//!
+//! JPEG/TIFF/DNG 直接写入 EXIF；其他格式，或无法在不改动文件结构的前提下写入时，
+//! 改写（或新建）XMP 附属文件。
+
+use crate::date::{CaptureDate, DateExtractor, DateSource};
+use crate::exifpatch::{self, Patched};
+use crate::group::{MediaGroup, MediaItem};
+use crate::metadata::Metadata;
+use crate::timeshift::TimeShifts;
+use crate::xmp;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// 写回选项
+pub struct FixOptions {
+    pub dates: DateExtractor,
+    pub shifts: TimeShifts,
+    /// 手动指定的日期，对所有文件生效
+    pub manual: Option<CaptureDate>,
+    pub dry_run: bool,
+    pub quiet: bool,
+}
+
+/// 写回统计
+#[derive(Default)]
+pub struct FixStats {
+    pub exif: usize,
+    pub xmp: usize,
+    pub unchanged: usize,
+    pub undated: usize,
+    pub errors: usize,
+}
+
+/// 处理所有文件组
+pub fn fix_dates(groups: &[MediaGroup], options: &FixOptions) -> FixStats {
+    let mut stats = FixStats::default();
+    for group in groups {
+        if let Err(e) = fix_group(group, options, &mut stats) {
+            stats.errors += 1;
+            eprintln!("⚠️  处理失败: {} — {}", group.items[0].path.display(), e);
+        }
+    }
+    stats
+}
+
+/// 确定组的日期及写回原因；EXIF 已正确或没有可靠日期时返回 None
+fn resolve(group: &MediaGroup, options: &FixOptions) -> Result<Option<(CaptureDate, String)>> {
+    if let Some(date) = options.manual {
+        return Ok(Some((date, "手动指定".to_string())));
+    }
+
+    let paths: Vec<&Path> = group.items.iter().map(|item| item.path.as_path()).collect();
+    let mut extracted = options.dates.extract(&paths)?;
+    let metadata = if options.shifts.needs_metadata() {
+        Metadata::for_group(group)
+    } else {
+        Metadata::default()
+    };
+    let shift = options.shifts.apply(&mut extracted, &metadata)?;
+
+    Ok(match (extracted, shift) {
+        (Some((date, _)), Some(shift)) => Some((date, format!("校正 {}", shift))),
+        (Some((date, DateSource::Filename)), None) => Some((date, DateSource::Filename.to_string())),
+        _ => None,
+    })
+}
+
+fn fix_group(group: &MediaGroup, options: &FixOptions, stats: &mut FixStats) -> Result<()> {
+    let Some((date, reason)) = resolve(group, options)? else {
+        // 区分 EXIF 已有日期与完全没有日期
+        let paths: Vec<&Path> = group.items.iter().map(|item| item.path.as_path()).collect();
+        match options.manual.is_none() && options.dates.extract(&paths)?.is_some() {
+            true => stats.unchanged += group.items.len(),
+            false => stats.undated += group.items.len(),
+        }
+        return Ok(());
+    };
+
+    let (time, offset) = (date.local(), date.offset());
+    let mut text = time.format("%Y-%m-%d %H:%M:%S").to_string();
+    if let Some(offset) = offset {
+        text.push_str(&format!(" {}", offset));
+    }
+    let prefix = if options.dry_run { "[预览] " } else { "" };
+
+    for item in &group.items {
+        let patched = if exifpatch::is_writable(&item.path) {
+            exifpatch::write_date(&item.path, time, offset, options.dry_run)?
+        } else {
+            None
+        };
+        let mut targets = Vec::new();
+        match patched {
+            Some(Patched::InPlace) => targets.push(format!("写入 EXIF: {}", item.path.display())),
+            Some(Patched::NewExif) => targets.push(format!("新建 EXIF: {}", item.path.display())),
+            Some(Patched::DateOnly) => {
+                targets.push(format!("写入 EXIF（无时区字段）: {}", item.path.display()))
+            }
+            None => {}
+        }
+        // EXIF 无法写入或缺少时区字段时，完整的日期记录在 XMP 附属文件中
+        if matches!(patched, None | Some(Patched::DateOnly)) {
+            let sidecar = xmp_path(item);
+            xmp::write_date(&sidecar, time, offset, options.dry_run)?;
+            targets.push(format!("写入 XMP: {}", sidecar.display()));
+        }
+        match patched {
+            Some(_) => stats.exif += 1,
+            None => stats.xmp += 1,
+        }
+
+        if !options.quiet {
+            for target in targets {
+                println!("  {}{} ← {} [{}]", prefix, target, text, reason);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 文件已有的 XMP 附属文件，否则为同目录下的 `<文件名主干>.xmp`
+///
+/// 使用文件自己的主干而不是组的主干：并入其他组的 Live Photo 视频（如 `IMG_E1234.MOV`）
+/// 不能写到静态图的 XMP 中。
+fn xmp_path(item: &MediaItem) -> PathBuf {
+    let stem = item.path.file_stem().unwrap_or_default().to_string_lossy();
+    item.sidecars
+        .iter()
+        .map(|sc| &sc.path)
+        .find(|path| path.extension().is_some_and(|e| e.eq_ignore_ascii_case("xmp")))
+        .cloned()
+        .unwrap_or_else(|| item.path.with_file_name(format!("{}.xmp", stem)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::group_by_stem;
+    use std::fs;
+
+    #[test]
+    fn writes_exif_or_own_xmp() {
+        let dir = std::env::temp_dir().join(format!("porg-fixdates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let jfif = b"\xFF\xD8\xFF\xE0\x00\x10JFIF\0\x01\x01\0\0\x01\0\x01\0\0\xFF\xD9";
+        fs::write(dir.join("IMG_1.JPG"), jfif).unwrap();
+        fs::write(dir.join("IMG_1.HEIC"), b"\0\0\0\x18ftypheic\0\0\0\0mif1heic").unwrap();
+        fs::write(dir.join("IMG_E1.MOV"), b"\0\0\0\x14ftypqt  \0\0\x02\0qt  ").unwrap();
+
+        let photos: Vec<PathBuf> =
+            ["IMG_1.JPG", "IMG_1.HEIC", "IMG_E1.MOV"].iter().map(|n| dir.join(n)).collect();
+        let mut groups = group_by_stem(photos, vec![Vec::new(); 3], false);
+        // 与配对后的 Live Photo 一样，视频并入静态图的组
+        let video = groups.pop().unwrap().items.pop().unwrap();
+        groups[0].items.push(video);
+
+        let options = FixOptions {
+            dates: DateExtractor::new(&[DateSource::Exif], &[]).unwrap(),
+            shifts: TimeShifts::default(),
+            manual: Some(crate::date::parse_manual_date("2023-06-15 10:15:00+09:00").unwrap()),
+            dry_run: false,
+            quiet: true,
+        };
+        let stats = fix_dates(&groups, &options);
+        assert_eq!((stats.exif, stats.xmp, stats.errors), (1, 2, 0));
+
+        let jpeg = fs::read(dir.join("IMG_1.JPG")).unwrap();
+        assert!(jpeg.windows(19).any(|w| w == b"2023:06:15 10:15:00"));
+        for name in ["IMG_1.xmp", "IMG_E1.xmp"] {
+            let xmp = fs::read_to_string(dir.join(name)).unwrap();
+            assert!(xmp.contains("exif:DateTimeOriginal=\"2023-06-15T10:15:00+09:00\""), "{}", xmp);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+//! 离线逆地理编码：根据 GPS 坐标查找最近的 GeoNames 城市
+//!
+//! 数据使用 GeoNames 的城市表（如 `cities15000.txt`，制表符分隔）。同目录下若有
+//! `admin1CodesASCII.txt` 与 `countryInfo.txt`，省/州与国家显示为名称，否则显示代码。
+//! 最近邻查询使用以单位球面三维坐标建立的 k-d 树，避免经度在 ±180° 处断开。
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// 逆地理编码结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Place {
+    pub city: String,
+    pub region: String,
+    pub country: String,
+}
+
+impl Place {
+    /// 用于报告的简短描述，如 "Shibuya, Tokyo, Japan"
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self.city.as_str()];
+        if !self.region.is_empty() && self.region != self.city {
+            parts.push(&self.region);
+        }
+        parts.push(&self.country);
+        parts.join(", ")
+    }
+}
+
+/// 城市表中的一条记录
+struct City {
+    name: String,
+    country_code: String,
+    admin1_code: String,
+    point: [f64; 3],
+}
+
+/// 基于 k-d 树的离线逆地理编码器
+pub struct Geocoder {
+    cities: Vec<City>,
+    /// k-d 树：按层交替以 x/y/z 为轴排列的城市下标
+    tree: Vec<usize>,
+    regions: HashMap<String, String>,
+    countries: HashMap<String, String>,
+}
+
+impl Geocoder {
+    /// 加载 GeoNames 城市表及同目录下的地区/国家名称表
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("无法读取地理数据: {}", path.display()))?;
+
+        let cities: Vec<City> = text.lines().filter_map(parse_city).collect();
+        if cities.is_empty() {
+            anyhow::bail!("地理数据中没有可用的城市记录（需要 GeoNames 城市表格式）: {}", path.display());
+        }
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        // 地区表: "JP.40\tTokyo\tTokyo\t1850144"
+        let regions = read_names(&dir.join("admin1CodesASCII.txt"), 0, 1);
+        // 国家表: "JP\tJPN\t392\tJA\tJapan\t..."
+        let countries = read_names(&dir.join("countryInfo.txt"), 0, 4);
+
+        let mut tree: Vec<usize> = (0..cities.len()).collect();
+        build(&cities, &mut tree, 0);
+
+        Ok(Geocoder {
+            cities,
+            tree,
+            regions,
+            countries,
+        })
+    }
+
+    /// 城市记录数
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    /// 查找离坐标最近的城市
+    pub fn lookup(&self, latitude: f64, longitude: f64) -> Option<Place> {
+        let target = to_point(latitude, longitude);
+        let mut best = None;
+        nearest(&self.cities, &self.tree, 0, &target, &mut best);
+        let city = &self.cities[best?.0];
+
+        let region = self
+            .regions
+            .get(&format!("{}.{}", city.country_code, city.admin1_code))
+            .cloned()
+            .unwrap_or_else(|| city.admin1_code.clone());
+        let country = self
+            .countries
+            .get(&city.country_code)
+            .cloned()
+            .unwrap_or_else(|| city.country_code.clone());
+
+        Some(Place {
+            city: city.name.clone(),
+            region,
+            country,
+        })
+    }
+}
+
+/// 解析城市表的一行：1 名称、4 纬度、5 经度、8 国家代码、10 一级行政区代码
+fn parse_city(line: &str) -> Option<City> {
+    if line.starts_with('#') {
+        return None;
+    }
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 11 {
+        return None;
+    }
+    let latitude: f64 = fields[4].parse().ok()?;
+    let longitude: f64 = fields[5].parse().ok()?;
+    Some(City {
+        name: fields[1].to_string(),
+        country_code: fields[8].to_string(),
+        admin1_code: fields[10].to_string(),
+        point: to_point(latitude, longitude),
+    })
+}
+
+/// 读取制表符分隔的代码→名称表；文件不存在时返回空表
+fn read_names(path: &Path, key: usize, value: usize) -> HashMap<String, String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            Some((fields.get(key)?.to_string(), fields.get(value)?.to_string()))
+        })
+        .collect()
+}
+
+/// 经纬度转换为单位球面上的三维坐标
+fn to_point(latitude: f64, longitude: f64) -> [f64; 3] {
+    let (lat, lon) = (latitude.to_radians(), longitude.to_radians());
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+fn distance2(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum()
+}
+
+/// 原地构建 k-d 树：切片中点为当前节点，左右两半为子树
+fn build(cities: &[City], nodes: &mut [usize], depth: usize) {
+    if nodes.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = nodes.len() / 2;
+    nodes.select_nth_unstable_by(mid, |&a, &b| {
+        cities[a].point[axis].total_cmp(&cities[b].point[axis])
+    });
+    let (left, right) = nodes.split_at_mut(mid);
+    build(cities, left, depth + 1);
+    build(cities, &mut right[1..], depth + 1);
+}
+
+fn nearest(
+    cities: &[City],
+    nodes: &[usize],
+    depth: usize,
+    target: &[f64; 3],
+    best: &mut Option<(usize, f64)>,
+) {
+    if nodes.is_empty() {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = nodes.len() / 2;
+    let index = nodes[mid];
+    let point = &cities[index].point;
+
+    let d = distance2(point, target);
+    if best.is_none_or(|(_, best_d)| d < best_d) {
+        *best = Some((index, d));
+    }
+
+    let diff = target[axis] - point[axis];
+    let (near, far) = if diff < 0.0 {
+        (&nodes[..mid], &nodes[mid + 1..])
+    } else {
+        (&nodes[mid + 1..], &nodes[..mid])
+    };
+    nearest(cities, near, depth + 1, target, best);
+    if best.is_none_or(|(_, best_d)| diff * diff < best_d) {
+        nearest(cities, far, depth + 1, target, best);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geocoder(rows: &[(&str, f64, f64, &str, &str)]) -> Geocoder {
+        let cities: Vec<City> = rows
+            .iter()
+            .map(|&(name, lat, lon, cc, admin1)| City {
+                name: name.to_string(),
+                country_code: cc.to_string(),
+                admin1_code: admin1.to_string(),
+                point: to_point(lat, lon),
+            })
+            .collect();
+        let mut tree: Vec<usize> = (0..cities.len()).collect();
+        build(&cities, &mut tree, 0);
+        Geocoder {
+            cities,
+            tree,
+            regions: HashMap::from([("JP.40".to_string(), "Tokyo".to_string())]),
+            countries: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn finds_nearest_city() {
+        let g = geocoder(&[
+            ("Tokyo", 35.6895, 139.6917, "JP", "40"),
+            ("Paris", 48.8534, 2.3488, "FR", "11"),
+            ("Suva", -18.1416, 178.4415, "FJ", "C"),
+            ("Apia", -13.8333, -171.7667, "WS", "4"),
+            ("Reykjavik", 64.1355, -21.8954, "IS", "39"),
+        ]);
+        let place = g.lookup(35.66, 139.70).unwrap();
+        assert_eq!(place.city, "Tokyo");
+        assert_eq!(place.region, "Tokyo");
+        assert_eq!(place.country, "JP");
+        assert_eq!(g.lookup(48.0, 3.0).unwrap().city, "Paris");
+        // 跨越 180° 经线
+        assert_eq!(g.lookup(-16.0, -179.9).unwrap().city, "Suva");
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let rows: Vec<(String, f64, f64)> = (0..300)
+            .map(|i| {
+                let lat = ((i * 37) % 180) as f64 - 89.5;
+                let lon = ((i * 91) % 360) as f64 - 179.5;
+                (format!("c{}", i), lat, lon)
+            })
+            .collect();
+        let refs: Vec<_> = rows.iter().map(|(n, a, o)| (n.as_str(), *a, *o, "XX", "")).collect();
+        let g = geocoder(&refs);
+        for (lat, lon) in [(10.3, 20.7), (-45.0, 170.0), (89.0, -179.0), (0.0, 0.0)] {
+            let target = to_point(lat, lon);
+            let expected = g
+                .cities
+                .iter()
+                .min_by(|a, b| distance2(&a.point, &target).total_cmp(&distance2(&b.point, &target)))
+                .unwrap();
+            assert_eq!(g.lookup(lat, lon).unwrap().city, expected.name);
+        }
+    }
+}
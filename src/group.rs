@@ -0,0 +1,184 @@
+//! 文件分组：同一目录下文件名主干相同的文件（如 RAW+JPEG）作为整体归档
+
+use crate::media;
+use crate::sidecar::Sidecar;
+use crate::sniff;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// 待整理的文件：主文件及其附属文件
+pub struct MediaItem {
+    pub path: PathBuf,
+    pub sidecars: Vec<Sidecar>,
+    /// 目标文件名中主干之后的部分，如 `.NEF`
+    suffix: String,
+    /// 按内容修正了扩展名（`suffix` 与源文件不同）
+    pub extension_fixed: bool,
+}
+
+impl MediaItem {
+    /// 使用组的目标主干得到该文件的目标文件名
+    pub fn target_name(&self, stem: &str) -> String {
+        format!("{}{}", stem, self.suffix)
+    }
+
+    /// 文件名中主干之后的部分（扩展名）
+    pub fn suffix(&self) -> &str {
+        &self.suffix
+    }
+}
+
+/// 同一目录下文件名主干相同的一组文件
+pub struct MediaGroup {
+    pub stem: String,
+    /// RAW 文件排在最前、视频排在最后，其余按路径排序
+    pub items: Vec<MediaItem>,
+}
+
+impl MediaGroup {
+    /// 按日期来源的优先级排列组内文件：RAW、其他照片、视频
+    pub fn sort_items(&mut self) {
+        self.items.sort_by_key(|item| {
+            (
+                !media::is_raw(&item.path),
+                crate::video::is_video(&item.path),
+                item.path.clone(),
+            )
+        });
+    }
+
+    /// 组内全部主文件和附属文件在给定主干下的目标文件名
+    pub fn target_names(&self, stem: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        for item in &self.items {
+            let name = item.target_name(stem);
+            names.extend(item.sidecars.iter().map(|sc| sc.target_name(&name)));
+            names.push(name);
+        }
+        names
+    }
+}
+
+/// 按 (目录, 文件名主干) 分组，`sidecars` 与 `photos` 一一对应。
+/// 扩展名不是支持的格式时整个文件名作为主干；`fix_extensions` 时按文件内容修正目标扩展名
+pub fn group_by_stem(
+    photos: Vec<PathBuf>,
+    sidecars: Vec<Vec<Sidecar>>,
+    fix_extensions: bool,
+) -> Vec<MediaGroup> {
+    let mut groups: BTreeMap<(PathBuf, String), Vec<MediaItem>> = BTreeMap::new();
+
+    for (path, sidecars) in photos.into_iter().zip(sidecars) {
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let stem = match Path::new(&name).file_stem() {
+            Some(stem) if media::is_supported(&path) => stem.to_string_lossy().to_string(),
+            _ => name.clone(),
+        };
+        let corrected = fix_extensions.then(|| sniff::corrected_extension(&path)).flatten();
+        let suffix = match &corrected {
+            Some(ext) => format!(".{}", ext),
+            None => name[stem.len()..].to_string(),
+        };
+        groups.entry((parent, stem)).or_default().push(MediaItem {
+            sidecars,
+            suffix,
+            extension_fixed: corrected.is_some(),
+            path,
+        });
+    }
+
+    groups
+        .into_iter()
+        .map(|((_, stem), mut items)| {
+            keep_distinct_suffixes(&stem, &mut items);
+            let mut group = MediaGroup { stem, items };
+            group.sort_items();
+            group
+        })
+        .collect()
+}
+
+/// 修正后的扩展名与组内其他文件重复时（如 `a.jpg` 实为 HEIC，同时存在 `a.heic`），保留原扩展名
+fn keep_distinct_suffixes(stem: &str, items: &mut [MediaItem]) {
+    for i in 0..items.len() {
+        if !items[i].extension_fixed {
+            continue;
+        }
+        let suffix = items[i].suffix.to_lowercase();
+        let duplicate =
+            (0..items.len()).any(|j| j != i && items[j].suffix.to_lowercase() == suffix);
+        if duplicate {
+            let name = items[i].path.file_name().unwrap_or_default().to_string_lossy();
+            items[i].suffix = name[stem.len()..].to_string();
+            items[i].extension_fixed = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(paths: &[&str]) -> Vec<MediaGroup> {
+        let photos: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        let sidecars = vec![Vec::new(); photos.len()];
+        group_by_stem(photos, sidecars, false)
+    }
+
+    fn paths(group: &MediaGroup) -> Vec<&Path> {
+        group.items.iter().map(|item| item.path.as_path()).collect()
+    }
+
+    #[test]
+    fn groups_by_directory_and_stem() {
+        let groups =
+            group(&["d/IMG_1.MOV", "d/IMG_1.JPG", "d/IMG_1.CR2", "e/IMG_1.jpg", "d/b.jpg"]);
+        assert_eq!(groups.len(), 3);
+
+        // RAW 在前，视频在后
+        assert_eq!(groups[0].stem, "IMG_1");
+        assert_eq!(
+            paths(&groups[0]),
+            [Path::new("d/IMG_1.CR2"), Path::new("d/IMG_1.JPG"), Path::new("d/IMG_1.MOV")]
+        );
+        assert_eq!(
+            groups[0].target_names("new"),
+            ["new.CR2", "new.JPG", "new.MOV"].map(String::from)
+        );
+        assert_eq!(groups[1].stem, "b");
+        assert_eq!(paths(&groups[2]), [Path::new("e/IMG_1.jpg")]);
+    }
+
+    #[test]
+    fn unsupported_extensions_keep_full_name() {
+        // 不支持的扩展名不去掉，避免 `a.jpg` 与 `a.bin` 被视为一组
+        let groups = group(&["d/a.jpg", "d/a.bin", "d/archive.tar.gz"]);
+        let stems: Vec<&str> = groups.iter().map(|g| g.stem.as_str()).collect();
+        assert_eq!(stems, ["a", "a.bin", "archive.tar.gz"]);
+        assert_eq!(groups[1].items[0].target_name("x"), "x");
+    }
+
+    #[test]
+    fn corrected_extension_must_stay_distinct() {
+        let dir = std::env::temp_dir().join(format!("porg-group-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+        for name in ["a.jpg", "a.heic", "b.jpg"] {
+            std::fs::write(dir.join(name), heic).unwrap();
+        }
+        let photos = ["a.jpg", "a.heic", "b.jpg"].map(|name| dir.join(name)).to_vec();
+        let groups = group_by_stem(photos, vec![Vec::new(); 3], true);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let suffixes: Vec<(&str, bool)> = groups
+            .iter()
+            .flat_map(|g| &g.items)
+            .map(|item| (item.suffix(), item.extension_fixed))
+            .collect();
+        assert_eq!(suffixes, [(".heic", false), (".jpg", false), (".heic", true)]);
+    }
+}
@@ -0,0 +1,149 @@
+//! 最小的 inotify 封装，供 `porg watch` 在目录变化时唤醒
+//!
+//! 代替 `notify` crate：离线构建环境无法引入该依赖，因此直接调用 Linux 的
+//! `inotify_init1`、`inotify_add_watch` 与 `poll`。只用于唤醒，不逐个解析事件，
+//! 每次唤醒后由调用方重新扫描目录。其他系统上 `Inotify::new` 返回错误，调用方退回定期扫描。
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// inotify 实例
+#[cfg(target_os = "linux")]
+pub struct Inotify {
+    file: std::fs::File,
+}
+
+#[cfg(target_os = "linux")]
+mod ffi {
+    use std::os::raw::{c_char, c_int, c_short, c_ulong};
+
+    pub const IN_NONBLOCK: c_int = 0o4000;
+    pub const IN_CLOEXEC: c_int = 0o2000000;
+    /// IN_CLOSE_WRITE | IN_MOVED_FROM | IN_MOVED_TO | IN_CREATE | IN_DELETE | IN_ONLYDIR；
+    /// 不监听 IN_MODIFY，写入中的文件由定时复查大小判断
+    pub const MASK: u32 = 0x0000_0008 | 0x0000_0040 | 0x0000_0080 | 0x0000_0100 | 0x0000_0200
+        | 0x0100_0000;
+    pub const POLLIN: c_short = 0x1;
+
+    /// `struct pollfd`
+    #[repr(C)]
+    pub struct PollFd {
+        pub fd: c_int,
+        pub events: c_short,
+        pub revents: c_short,
+    }
+
+    extern "C" {
+        pub fn inotify_init1(flags: c_int) -> c_int;
+        pub fn inotify_add_watch(fd: c_int, path: *const c_char, mask: u32) -> c_int;
+        pub fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Inotify {
+    pub fn new() -> io::Result<Inotify> {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: 只传入标志位；成功时返回的描述符归 File 所有
+        let fd = unsafe { ffi::inotify_init1(ffi::IN_NONBLOCK | ffi::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Inotify {
+            file: unsafe { std::fs::File::from_raw_fd(fd) },
+        })
+    }
+
+    /// 监视目录（重复添加没有副作用）；目录已被删除时忽略
+    pub fn add(&mut self, dir: &Path) -> io::Result<()> {
+        use std::ffi::CString;
+        use std::os::fd::AsRawFd;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: 描述符有效，路径是以 NUL 结尾的 C 字符串
+        let wd = unsafe { ffi::inotify_add_watch(self.file.as_raw_fd(), path.as_ptr(), ffi::MASK) };
+        if wd < 0 {
+            let err = io::Error::last_os_error();
+            // 扫描后目录已被删除：下次扫描不会再出现
+            if err.kind() == io::ErrorKind::NotFound {
+                return Ok(());
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// 等待任一监视的目录发生变化，最多等待 `timeout`，并读空事件队列
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        use std::io::Read;
+        use std::os::fd::AsRawFd;
+
+        let mut fd = ffi::PollFd {
+            fd: self.file.as_raw_fd(),
+            events: ffi::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+        // SAFETY: 传入一个有效的 pollfd
+        if unsafe { ffi::poll(&mut fd, 1, millis) } < 0 {
+            let err = io::Error::last_os_error();
+            // 被信号打断时当作一次唤醒
+            return if err.kind() == io::ErrorKind::Interrupted { Ok(()) } else { Err(err) };
+        }
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.file.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// 其他系统没有 inotify
+#[cfg(not(target_os = "linux"))]
+pub struct Inotify;
+
+#[cfg(not(target_os = "linux"))]
+impl Inotify {
+    pub fn new() -> io::Result<Inotify> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "当前系统不支持"))
+    }
+
+    pub fn add(&mut self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn wait(&mut self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Instant;
+
+    #[test]
+    fn wakes_on_new_file() {
+        let dir = std::env::temp_dir().join(format!("porg-inotify-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut inotify = Inotify::new().unwrap();
+        inotify.add(&dir).unwrap();
+        inotify.add(&dir.join("missing")).unwrap();
+
+        fs::write(dir.join("a.jpg"), b"x").unwrap();
+        let started = Instant::now();
+        inotify.wait(Duration::from_secs(10)).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+//! 操作日志：记录每次运行中复制/移动的文件，供 `porg undo` 撤销
+
+use crate::hash::Digest;
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// 日志目录（位于输出目录下）
+const JOURNAL_DIR: &str = ".porg/journal";
+/// 没有记录内容摘要时写入的占位符
+const NO_DIGEST: &str = "-";
+/// 日志文件首行
+const HEADER: &str = "# porg journal v1";
+/// 日志文件扩展名
+const EXT: &str = "log";
+/// 已撤销日志的扩展名
+const UNDONE_EXT: &str = "undone";
+
+/// 文件操作类型
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Copy,
+    Move,
+    Hardlink,
+    Symlink,
+    Reflink,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Copy => "copy",
+            Operation::Move => "move",
+            Operation::Hardlink => "hardlink",
+            Operation::Symlink => "symlink",
+            Operation::Reflink => "reflink",
+        })
+    }
+}
+
+impl FromStr for Operation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "copy" => Ok(Operation::Copy),
+            "move" => Ok(Operation::Move),
+            "hardlink" => Ok(Operation::Hardlink),
+            "symlink" => Ok(Operation::Symlink),
+            "reflink" => Ok(Operation::Reflink),
+            _ => anyhow::bail!("未知的操作类型: {}", s),
+        }
+    }
+}
+
+/// 日志中的一条记录
+#[derive(Debug)]
+pub struct Entry {
+    pub timestamp: String,
+    pub operation: Operation,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// 目标文件的内容摘要；链接与克隆只在去重已算出时记录
+    pub digest: Option<Digest>,
+}
+
+/// 一次运行的操作日志（首次写入时才创建文件）
+pub struct Journal {
+    run_id: String,
+    path: PathBuf,
+    file: Option<fs::File>,
+}
+
+impl Journal {
+    /// 为本次运行分配运行 ID
+    pub fn new(output_dir: &Path) -> Self {
+        let dir = output_dir.join(JOURNAL_DIR);
+        let base = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut run_id = base.clone();
+        let mut n = 1;
+        while dir.join(format!("{}.{}", run_id, EXT)).exists()
+            || dir.join(format!("{}.{}", run_id, UNDONE_EXT)).exists()
+        {
+            run_id = format!("{}-{}", base, n);
+            n += 1;
+        }
+        let path = dir.join(format!("{}.{}", run_id, EXT));
+        Journal {
+            run_id,
+            path,
+            file: None,
+        }
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// 是否已写入过记录
+    pub fn is_empty(&self) -> bool {
+        self.file.is_none()
+    }
+
+    /// 追加一条记录并立即落盘，中途中断也能撤销已完成的部分
+    pub fn record(
+        &mut self,
+        operation: Operation,
+        source: &Path,
+        destination: &Path,
+        digest: Option<Digest>,
+    ) -> Result<()> {
+        if self.file.is_none() {
+            let dir = self.path.parent().context("无效的日志路径")?;
+            fs::create_dir_all(dir)
+                .with_context(|| format!("无法创建日志目录: {}", dir.display()))?;
+            let mut file = fs::OpenOptions::new()
+                .create_new(true)
+                .append(true)
+                .open(&self.path)
+                .with_context(|| format!("无法创建日志文件: {}", self.path.display()))?;
+            writeln!(file, "{}", HEADER)?;
+            self.file = Some(file);
+        }
+        let file = self.file.as_mut().context("日志文件未打开")?;
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{}\t{}",
+            Local::now().to_rfc3339(),
+            operation,
+            escape(&source.to_string_lossy()),
+            escape(&destination.to_string_lossy()),
+            digest.map_or_else(|| NO_DIGEST.to_string(), |d| d.to_string())
+        )
+        .and_then(|_| file.flush())
+        .with_context(|| format!("无法写入日志文件: {}", self.path.display()))
+    }
+}
+
+/// 查找日志文件：指定运行 ID，或最近一次尚未撤销的运行
+pub fn find(output_dir: &Path, run_id: Option<&str>) -> Result<PathBuf> {
+    let dir = output_dir.join(JOURNAL_DIR);
+    if let Some(id) = run_id {
+        let path = dir.join(format!("{}.{}", id, EXT));
+        if path.exists() {
+            return Ok(path);
+        }
+        if dir.join(format!("{}.{}", id, UNDONE_EXT)).exists() {
+            anyhow::bail!("运行 {} 已被撤销", id);
+        }
+        anyhow::bail!("找不到运行 {} 的日志: {}", id, path.display());
+    }
+
+    let mut logs: Vec<PathBuf> = fs::read_dir(&dir)
+        .with_context(|| format!("找不到操作日志目录: {}", dir.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some(EXT))
+        .collect();
+    logs.sort_by_cached_key(|path| run_order(&run_id_of(path)));
+    logs.pop()
+        .with_context(|| format!("没有可撤销的运行: {}", dir.display()))
+}
+
+/// 日志文件对应的运行 ID
+pub fn run_id_of(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// 运行 ID 的先后顺序：先按时间，同一秒内再按数字后缀（`X` < `X-1` < `X-2` < `X-10`）
+fn run_order(run_id: &str) -> (String, u64) {
+    // 时间部分形如 `20240101-120000`
+    const TIME_LEN: usize = 15;
+    let suffix = match run_id.get(TIME_LEN..) {
+        Some("") => Some(0),
+        Some(rest) => rest.strip_prefix('-').and_then(|n| n.parse().ok()),
+        None => None,
+    };
+    match suffix {
+        Some(n) => (run_id[..TIME_LEN].to_string(), n),
+        None => (run_id.to_string(), 0),
+    }
+}
+
+/// 读取日志中的全部记录
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("无法读取日志文件: {}", path.display()))?;
+    let mut entries = Vec::new();
+    for (lineno, line) in content.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = parse_line(line)
+            .with_context(|| format!("日志格式错误: {}:{}", path.display(), lineno + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// 将日志标记为已撤销
+pub fn mark_undone(path: &Path) -> Result<()> {
+    let undone = path.with_extension(UNDONE_EXT);
+    fs::rename(path, &undone)
+        .with_context(|| format!("无法标记日志为已撤销: {}", path.display()))
+}
+
+fn parse_line(line: &str) -> Result<Entry> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let [timestamp, operation, source, destination, digest] = fields[..] else {
+        anyhow::bail!("字段数量应为 5，实际为 {}", fields.len());
+    };
+    Ok(Entry {
+        timestamp: timestamp.to_string(),
+        operation: operation.parse()?,
+        source: PathBuf::from(unescape(source)),
+        destination: PathBuf::from(unescape(destination)),
+        digest: match digest {
+            NO_DIGEST => None,
+            digest => Some(digest.parse()?),
+        },
+    })
+}
+
+/// 转义路径中的制表符、换行和反斜杠
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// 还原 `escape` 转义的文本
+pub fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("porg-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        for s in ["plain.jpg", "a\tb", "tab\there", "new\nline\r\\", "尾部\\"] {
+            let escaped = escape(s);
+            assert!(!escaped.contains(['\t', '\n', '\r']));
+            assert_eq!(unescape(&escaped), s);
+        }
+        assert_eq!(escape("a\tb\\c"), "a\\tb\\\\c");
+        assert_eq!(unescape("dangling\\"), "dangling\\");
+    }
+
+    #[test]
+    fn record_and_read() {
+        let dir = temp_dir("journal");
+        let digest: Digest = "ab".repeat(32).parse().unwrap();
+        let mut journal = Journal::new(&dir);
+        assert!(journal.is_empty());
+        let (source, destination) = (Path::new("/src/a\tb.jpg"), Path::new("/out/x.jpg"));
+        journal.record(Operation::Move, source, destination, Some(digest)).unwrap();
+        let (source, destination) = (Path::new("/src/c.jpg"), Path::new("/out/c.jpg"));
+        journal.record(Operation::Symlink, source, destination, Some(digest)).unwrap();
+        let (source, destination) = (Path::new("/src/d.jpg"), Path::new("/out/d.jpg"));
+        journal.record(Operation::Hardlink, source, destination, None).unwrap();
+
+        let path = find(&dir, None).unwrap();
+        assert_eq!(run_id_of(&path), journal.run_id());
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].operation, Operation::Move);
+        assert_eq!(entries[0].source, Path::new("/src/a\tb.jpg"));
+        assert_eq!(entries[1].destination, Path::new("/out/c.jpg"));
+        assert_eq!(entries[1].digest, Some(digest));
+        assert_eq!((entries[2].operation, entries[2].digest), (Operation::Hardlink, None));
+
+        mark_undone(&path).unwrap();
+        assert!(find(&dir, Some(journal.run_id())).is_err());
+        assert!(find(&dir, None).is_err());
+        // 已撤销的运行 ID 不会被再次分配
+        assert_ne!(Journal::new(&dir).run_id(), journal.run_id());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_latest_run() {
+        let dir = temp_dir("journal-find");
+        let logs = dir.join(JOURNAL_DIR);
+        fs::create_dir_all(&logs).unwrap();
+        for id in [
+            "20240101-120000-10",
+            "20240101-120000",
+            "20240101-120000-9",
+            "20240101-120000-1",
+            "20231231-235959-11",
+        ] {
+            fs::write(logs.join(format!("{}.{}", id, EXT)), HEADER).unwrap();
+        }
+        fs::write(logs.join(format!("20240102-000000.{}", UNDONE_EXT)), HEADER).unwrap();
+        assert_eq!(run_id_of(&find(&dir, None).unwrap()), "20240101-120000-10");
+        assert!(run_order("20240101-120000") < run_order("20240101-120000-1"));
+        assert!(run_order("20240101-120000-9") < run_order("20240101-120000-10"));
+        assert!(run_order("20240101-120000-99") < run_order("20240101-120001"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+//! 输出目录模板（`--format`）
+//!
+//! 占位符 `{name}` / `{name:arg}` 替换为元数据，其余文字按 strftime 格式化，
+//! 因此 `%Y-%m-%d` 与 `{make}/{date:%Y}/{date:%m-%d}` 都是合法的模板。
+
+use crate::metadata::Metadata;
+use crate::template::{sanitize, validate_strftime, Segment, Template};
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::path::{Path, PathBuf};
+
+/// 目录模板可用的占位符
+pub const DIR_PLACEHOLDERS: &[&str] = &[
+    "date",
+    "year",
+    "month",
+    "day",
+    "make",
+    "model",
+    "lens",
+    "ext",
+    "source_dir",
+    "country",
+    "region",
+    "city",
+];
+
+/// 需要读取文件元数据的占位符
+const METADATA_PLACEHOLDERS: &[&str] = &["make", "model", "lens", "country", "region", "city"];
+
+/// `{date}` 的默认格式
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// 渲染目录所需的信息
+pub struct DirContext<'a> {
+    /// 归档时间
+    pub time: NaiveDateTime,
+    pub metadata: &'a Metadata,
+    /// 主文件的扩展名（含 `.`）
+    pub ext: &'a str,
+    /// 源文件所在目录（相对源目录）
+    pub source_dir: &'a Path,
+}
+
+/// 解析后的目录模板
+pub struct DirLayout {
+    template: Template,
+}
+
+impl DirLayout {
+    pub fn new(source: &str) -> Result<Self> {
+        let template = Template::parse(source)?;
+        template.check_names(DIR_PLACEHOLDERS)?;
+        for segment in template.segments() {
+            match segment {
+                Segment::Literal(text) => validate_strftime(text)?,
+                Segment::Placeholder { name, arg: Some(arg) } if name == "date" => {
+                    validate_strftime(arg)?
+                }
+                _ => {}
+            }
+        }
+
+        // 用示例数据试渲染一次，提前发现 `..`、绝对路径等不安全的写法
+        let layout = DirLayout { template };
+        let sample = Metadata {
+            make: Some("Make".to_string()),
+            model: Some("Model".to_string()),
+            lens: Some("Lens".to_string()),
+            serial: None,
+            gps: None,
+            country: Some("Country".to_string()),
+            region: Some("Region".to_string()),
+            city: Some("City".to_string()),
+        };
+        let time = NaiveDate::from_ymd_opt(2000, 1, 1)
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .expect("合法日期");
+        layout.render(&DirContext {
+            time,
+            metadata: &sample,
+            ext: ".jpg",
+            source_dir: Path::new("dir"),
+        })?;
+        Ok(layout)
+    }
+
+    /// 模板是否用到需要读取 EXIF/XMP 的占位符
+    pub fn needs_metadata(&self) -> bool {
+        METADATA_PLACEHOLDERS.iter().any(|name| self.template.uses(name))
+    }
+
+    /// 生成相对输出目录的子目录
+    pub fn render(&self, ctx: &DirContext) -> Result<PathBuf> {
+        let mut out = String::new();
+        for segment in self.template.segments() {
+            match segment {
+                Segment::Literal(text) => out.push_str(&ctx.time.format(text).to_string()),
+                Segment::Placeholder { name, arg } => {
+                    let value = match name.as_str() {
+                        "date" => ctx
+                            .time
+                            .format(arg.as_deref().unwrap_or(DEFAULT_DATE_FORMAT))
+                            .to_string(),
+                        "year" => ctx.time.format("%Y").to_string(),
+                        "month" => ctx.time.format("%m").to_string(),
+                        "day" => ctx.time.format("%d").to_string(),
+                        "make" => sanitize(ctx.metadata.make.as_deref().unwrap_or("")),
+                        "model" => sanitize(ctx.metadata.model.as_deref().unwrap_or("")),
+                        "lens" => sanitize(ctx.metadata.lens.as_deref().unwrap_or("")),
+                        "country" => sanitize(ctx.metadata.country.as_deref().unwrap_or("")),
+                        "region" => sanitize(ctx.metadata.region.as_deref().unwrap_or("")),
+                        "city" => sanitize(ctx.metadata.city.as_deref().unwrap_or("")),
+                        "ext" => sanitize(&ctx.ext.trim_start_matches('.').to_lowercase()),
+                        // 保留源目录的层级结构
+                        "source_dir" => ctx
+                            .source_dir
+                            .components()
+                            .map(|c| sanitize(&c.as_os_str().to_string_lossy()))
+                            .collect::<Vec<_>>()
+                            .join("/"),
+                        _ => unreachable!("占位符已在解析时校验"),
+                    };
+                    out.push_str(&value);
+                }
+            }
+        }
+
+        check_path(&self.template, &out)?;
+        // 忽略空的路径片段（如源文件位于源目录顶层时的 {source_dir}）
+        Ok(out.split('/').filter(|part| !part.is_empty()).collect())
+    }
+}
+
+/// 拒绝会逃出输出目录或无法创建的路径
+fn check_path(template: &Template, path: &str) -> Result<()> {
+    if path.contains('\0') {
+        anyhow::bail!("目录模板 `{}` 生成的路径包含 NUL 字符", template);
+    }
+    if path.starts_with(['/', '\\']) || Path::new(path).has_root() {
+        anyhow::bail!("目录模板 `{}` 生成了绝对路径: {}", template, path);
+    }
+    if let Some(part) = path.split(['/', '\\']).find(|part| matches!(*part, "." | "..")) {
+        anyhow::bail!(
+            "目录模板 `{}` 生成的路径 `{}` 包含不安全的片段 `{}`",
+            template,
+            path,
+            part
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_specifiers() {
+        for format in ["%Q", "%Y/%", "{date:%Y-%Q}", "%Y-%z"] {
+            assert!(DirLayout::new(format).is_err(), "{}", format);
+        }
+        for format in ["%Y-%m-%d", "%Y/%-m", "{make}/{date:%Y}/{date:%m-%d}", "100%%"] {
+            assert!(DirLayout::new(format).is_ok(), "{}", format);
+        }
+    }
+
+    #[test]
+    fn rejects_unsafe_paths() {
+        for format in ["/photos/%Y", "%Y/../x", "{date:%Y/..}", "./%Y"] {
+            assert!(DirLayout::new(format).is_err(), "{}", format);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Apple Live Photo 配对：照片与视频通过共享的 ContentIdentifier 关联
+
+use crate::group::MediaGroup;
+use crate::media;
+use crate::video;
+use exif::{In, Reader, Tag, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+/// 可能作为 Live Photo 静态图的扩展名
+const STILL_EXTENSIONS: &[&str] = &["heic", "heif", "jpg", "jpeg"];
+
+/// Live Photo 视频的扩展名
+const MOTION_EXTENSIONS: &[&str] = &["mov"];
+
+/// Apple MakerNote 的文件头
+const APPLE_MAKER_NOTE_HEADER: &[u8] = b"Apple iOS\0";
+
+/// Apple MakerNote 中 ContentIdentifier 的标签号
+const APPLE_CONTENT_ID_TAG: u16 = 0x0011;
+
+/// 将 Live Photo 视频并入其静态图所在的组，返回配对数量
+///
+/// 视频改用静态图的文件名主干，即使原文件名不同（如 `IMG_1234.HEIC` 与 `IMG_E1234.MOV`）。
+/// 静态图只在视频所在的目录中查找。
+pub fn pair(groups: &mut Vec<MediaGroup>) -> usize {
+    // 带 ContentIdentifier 的视频：(所在组, 组内位置, 标识)
+    let mut motions = Vec::new();
+    for (gi, group) in groups.iter().enumerate() {
+        for (ii, item) in group.items.iter().enumerate() {
+            if !media::has_extension(&item.path, MOTION_EXTENSIONS) {
+                continue;
+            }
+            let id = video::read_apple_metadata(&item.path, video::APPLE_CONTENT_ID_KEY)
+                .ok()
+                .flatten()
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty());
+            if let Some(id) = id {
+                motions.push((gi, ii, id));
+            }
+        }
+    }
+    if motions.is_empty() {
+        return 0;
+    }
+
+    // 只读取与这些视频同目录的静态图的 EXIF，全部找到后即停止
+    let dirs: HashSet<&Path> = motions
+        .iter()
+        .filter_map(|&(gi, ii, _)| groups[gi].items[ii].path.parent())
+        .collect();
+    let mut wanted: HashSet<&str> = motions.iter().map(|(_, _, id)| id.as_str()).collect();
+    // ContentIdentifier → 静态图所在组
+    let mut stills: HashMap<String, usize> = HashMap::new();
+    'groups: for (gi, group) in groups.iter().enumerate() {
+        for item in &group.items {
+            if !media::has_extension(&item.path, STILL_EXTENSIONS)
+                || !item.path.parent().is_some_and(|dir| dirs.contains(dir))
+            {
+                continue;
+            }
+            if let Some(id) = still_content_id(&item.path) {
+                if wanted.remove(id.as_str()) {
+                    stills.insert(id, gi);
+                    if wanted.is_empty() {
+                        break 'groups;
+                    }
+                }
+            }
+        }
+    }
+
+    merge(groups, motions, &stills)
+}
+
+/// 把视频 `(所在组, 组内位置, 标识)` 移入标识相同的静态图所在的组，返回配对数量
+///
+/// 每张静态图最多并入一个视频：目标组中已有同扩展名的文件时（如编辑后导出的 `IMG_E1234.MOV`
+/// 与原视频标识相同），该视频留在自己的组中，避免两个文件得到同一个目标文件名。
+fn merge(
+    groups: &mut Vec<MediaGroup>,
+    motions: Vec<(usize, usize, String)>,
+    stills: &HashMap<String, usize>,
+) -> usize {
+    // 找出需要移动的视频：(所在组, 组内位置, 目标组)
+    let mut moves = Vec::new();
+    // 已有视频移入的 (目标组, 小写扩展名)
+    let mut taken = HashSet::new();
+    let mut paired = 0;
+    for (gi, ii, id) in motions {
+        let Some(&target) = stills.get(&id) else {
+            continue;
+        };
+        if target != gi {
+            let suffix = groups[gi].items[ii].suffix().to_lowercase();
+            let clash = groups[target]
+                .items
+                .iter()
+                .any(|item| item.suffix().to_lowercase() == suffix);
+            if clash || !taken.insert((target, suffix)) {
+                continue;
+            }
+            moves.push((gi, ii, target));
+        }
+        paired += 1;
+    }
+
+    // 倒序取出，保证组内位置不失效
+    for &(gi, ii, target) in moves.iter().rev() {
+        let item = groups[gi].items.remove(ii);
+        groups[target].items.push(item);
+    }
+    for &(_, _, target) in &moves {
+        groups[target].sort_items();
+    }
+    groups.retain(|g| !g.items.is_empty());
+
+    paired
+}
+
+/// 读取照片 Apple MakerNote 中的 ContentIdentifier
+fn still_content_id(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let exif = Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let field = exif.get_field(Tag::MakerNote, In::PRIMARY)?;
+    let Value::Undefined(data, _) = &field.value else {
+        return None;
+    };
+    parse_apple_maker_note(data, APPLE_CONTENT_ID_TAG)
+}
+
+/// 解析 Apple MakerNote（`Apple iOS\0` + 版本 + `MM` + IFD），读取 ASCII 标签
+///
+/// 标签值的偏移量相对于 MakerNote 起始位置。
+fn parse_apple_maker_note(data: &[u8], tag: u16) -> Option<String> {
+    if !data.starts_with(APPLE_MAKER_NOTE_HEADER) || data.get(12..14)? != b"MM" {
+        return None;
+    }
+    let read_u16 = |pos: usize| Some(u16::from_be_bytes(data.get(pos..pos + 2)?.try_into().ok()?));
+    let read_u32 = |pos: usize| Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?));
+
+    let count = read_u16(14)? as usize;
+    for i in 0..count {
+        let entry = 16 + i * 12;
+        if read_u16(entry)? != tag {
+            continue;
+        }
+        // 类型 2 为 ASCII
+        if read_u16(entry + 2)? != 2 {
+            return None;
+        }
+        let len = read_u32(entry + 4)? as usize;
+        let bytes = if len <= 4 {
+            data.get(entry + 8..entry + 8 + len)?
+        } else {
+            let offset = read_u32(entry + 8)? as usize;
+            data.get(offset..offset.checked_add(len)?)?
+        };
+        let value = String::from_utf8_lossy(bytes);
+        let value = value.trim_end_matches('\0').trim();
+        return (!value.is_empty()).then(|| value.to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::{self, MediaItem};
+    use std::path::PathBuf;
+
+    /// 构造只含一个 ASCII 标签的 Apple MakerNote
+    fn maker_note(tag: u16, value: &[u8]) -> Vec<u8> {
+        let mut data = APPLE_MAKER_NOTE_HEADER.to_vec();
+        data.extend_from_slice(&[0, 1]);
+        data.extend_from_slice(b"MM");
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&tag.to_be_bytes());
+        data.extend_from_slice(&2u16.to_be_bytes());
+        data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        if value.len() <= 4 {
+            let mut inline = value.to_vec();
+            inline.resize(4, 0);
+            data.extend_from_slice(&inline);
+        } else {
+            // 值紧跟在 IFD 之后：16 字节头部 + 12 字节条目 + 4 字节下一个 IFD
+            data.extend_from_slice(&32u32.to_be_bytes());
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(value);
+        }
+        data
+    }
+
+    fn names(groups: &[MediaGroup]) -> Vec<Vec<String>> {
+        let name = |item: &MediaItem| item.path.file_name().unwrap().to_string_lossy().to_string();
+        groups.iter().map(|g| g.items.iter().map(name).collect()).collect()
+    }
+
+    #[test]
+    fn attaches_at_most_one_motion_per_still() {
+        let paths = ["/a/IMG_1234.HEIC", "/a/IMG_E1234.MOV", "/a/IMG_F1234.MOV"];
+        let photos: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        let mut groups = group::group_by_stem(photos, vec![Vec::new(); 3], false);
+        let motions = vec![(1, 0, "id".to_string()), (2, 0, "id".to_string())];
+        let stills = HashMap::from([("id".to_string(), 0)]);
+        assert_eq!(merge(&mut groups, motions, &stills), 1);
+        assert_eq!(
+            names(&groups),
+            [vec!["IMG_1234.HEIC", "IMG_E1234.MOV"], vec!["IMG_F1234.MOV"]]
+        );
+
+        // 静态图已与同名视频成组时，编辑后导出的视频保留在自己的组中
+        let paths = ["/a/IMG_1234.HEIC", "/a/IMG_1234.MOV", "/a/IMG_E1234.MOV"];
+        let photos: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        let mut groups = group::group_by_stem(photos, vec![Vec::new(); 3], false);
+        let motions = vec![(0, 1, "id".to_string()), (1, 0, "id".to_string())];
+        assert_eq!(merge(&mut groups, motions, &stills), 1);
+        assert_eq!(
+            names(&groups),
+            [vec!["IMG_1234.HEIC", "IMG_1234.MOV"], vec!["IMG_E1234.MOV"]]
+        );
+    }
+
+    #[test]
+    fn reads_content_identifier() {
+        let id = b"6F2A9C3E-1B4D-4E5F-8A7B-0C1D2E3F4A5B\0";
+        let data = maker_note(APPLE_CONTENT_ID_TAG, id);
+        assert_eq!(
+            parse_apple_maker_note(&data, APPLE_CONTENT_ID_TAG).as_deref(),
+            Some("6F2A9C3E-1B4D-4E5F-8A7B-0C1D2E3F4A5B")
+        );
+        let short = maker_note(APPLE_CONTENT_ID_TAG, b"ab\0");
+        assert_eq!(parse_apple_maker_note(&short, APPLE_CONTENT_ID_TAG).as_deref(), Some("ab"));
+        assert_eq!(parse_apple_maker_note(&data, 0x0008), None);
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_maker_notes() {
+        let data = maker_note(APPLE_CONTENT_ID_TAG, b"6F2A9C3E-1B4D\0");
+        // 任意位置截断
+        for len in 0..data.len() {
+            assert_eq!(parse_apple_maker_note(&data[..len], APPLE_CONTENT_ID_TAG), None);
+        }
+
+        let mut other_vendor = data.clone();
+        other_vendor[..5].copy_from_slice(b"Nikon");
+        assert_eq!(parse_apple_maker_note(&other_vendor, APPLE_CONTENT_ID_TAG), None);
+
+        let mut little_endian = data.clone();
+        little_endian[12..14].copy_from_slice(b"II");
+        assert_eq!(parse_apple_maker_note(&little_endian, APPLE_CONTENT_ID_TAG), None);
+
+        // 非 ASCII 类型
+        let mut wrong_type = data.clone();
+        wrong_type[18..20].copy_from_slice(&3u16.to_be_bytes());
+        assert_eq!(parse_apple_maker_note(&wrong_type, APPLE_CONTENT_ID_TAG), None);
+
+        // 偏移量或长度越界
+        let mut bad_offset = data.clone();
+        bad_offset[24..28].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(parse_apple_maker_note(&bad_offset, APPLE_CONTENT_ID_TAG), None);
+        let mut bad_len = data.clone();
+        bad_len[20..24].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(parse_apple_maker_note(&bad_len, APPLE_CONTENT_ID_TAG), None);
+
+        // 条目数大于实际条目
+        let mut bad_count = data;
+        bad_count[14..16].copy_from_slice(&100u16.to_be_bytes());
+        bad_count[16..18].copy_from_slice(&0x0008u16.to_be_bytes());
+        assert_eq!(parse_apple_maker_note(&bad_count, APPLE_CONTENT_ID_TAG), None);
+    }
+}
@@ -1,19 +1,63 @@
 use anyhow::{Context, Result};
-use chrono::NaiveDateTime;
-use clap::Parser;
-use exif::{In, Reader, Tag};
-use std::collections::HashMap;
-use std::fs;
-use std::io::BufReader;
+use clap::{ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+mod config;
+mod date;
+mod dedup;
+mod event;
+mod exifpatch;
+mod filename;
+mod filter;
+mod fixdates;
+mod geocode;
+mod group;
+mod hash;
+mod inotify;
+mod journal;
+mod layout;
+mod livephoto;
+mod media;
+mod metadata;
+mod organize;
+mod pattern;
+mod pool;
+mod preserve;
+mod rename;
+mod sidecar;
+mod sniff;
+mod template;
+mod timeshift;
+mod toml;
+mod transfer;
+mod undo;
+mod video;
+mod watch;
+mod xmp;
+
+use chrono::TimeDelta;
+use config::Config;
+use date::{CaptureDate, DateExtractor, DateSource, TzMode};
+use event::{EventLabel, GroupBy};
+use filter::Filter;
+use group::MediaGroup;
+use organize::Organizer;
+use preserve::{Attribute, Preserve};
+use timeshift::{ShiftRule, TimeShifts};
+use transfer::Mode;
+
 /// 📷 photo-organizer — 按拍照日期自动分类照片
 ///
 /// 最简用法：在照片目录下直接运行 `photo-organizer`
 #[derive(Parser, Debug)]
-#[command(name = "porg", version, about, long_about = None)]
+#[command(name = "porg", version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// 照片源目录路径（默认: 当前目录）
     #[arg(default_value = ".")]
     source: PathBuf,
@@ -22,14 +66,98 @@ struct Cli {
     #[arg(short, long)]
     output: Option<PathBuf>,
 
-    /// 日期目录格式（默认: "%Y-%m-%d"）
-    #[arg(short, long, default_value = "%Y-%m-%d")]
+    /// 目录模板：strftime 格式加占位符 {date:FMT} {year} {month} {day} {make} {model}
+    /// {lens} {ext} {source_dir} {country}，如 "{make}/{date:%Y}/{date:%m-%d}"（默认: "%Y-%m-%d"）
+    #[arg(short, long, value_name = "TEMPLATE", default_value = "%Y-%m-%d")]
     format: String,
 
-    /// 移动文件而非复制
-    #[arg(short = 'm', long)]
+    /// 目录分组方式：day（每张照片按自己的日期）或 event（按拍摄间隔聚类为事件）
+    #[arg(long, value_enum, default_value = "day")]
+    group_by: GroupBy,
+
+    /// 事件之间的最小间隔，如 4h、90m、1h30m（用于 --group-by event）
+    #[arg(long, value_name = "DURATION", default_value = "4h", value_parser = event::parse_duration)]
+    event_gap: TimeDelta,
+
+    /// 事件目录名后附加出现最多的地点（用于 --group-by event）
+    #[arg(long, value_enum, value_name = "FIELD")]
+    event_label: Option<EventLabel>,
+
+    /// 相机时钟校正 [相机=]±HH:MM[:SS]（可多次指定），相机为序列号、"品牌 型号"、型号或品牌，
+    /// 省略时对所有文件生效；只校正 EXIF/视频元数据中的日期
+    #[arg(long = "time-shift", value_name = "[CAMERA=]OFFSET")]
+    time_shift: Vec<ShiftRule>,
+
+    /// 配置文件（默认读取 $XDG_CONFIG_HOME/porg/porg.toml 与源目录下的 porg.toml），
+    /// 键名为长选项名，命令行中的选项优先
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// 使用配置文件中 [profile.NAME] 的设置
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// 打印合并配置文件与命令行后生效的全部选项，然后退出
+    #[arg(long)]
+    print_config: bool,
+
+    /// 归档时区：capture（按拍摄地本地时间）、system（换算为本机时区）、utc 或 ±HH:MM
+    #[arg(long, default_value = "capture")]
+    tz: TzMode,
+
+    /// 日期来源及其优先顺序，逗号分隔：exif、filename、btime（创建时间）、mtime（修改时间）
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "exif,filename")]
+    fallback: Vec<DateSource>,
+
+    /// 只整理匹配的文件（gitignore 风格通配符，可多次指定），如 "*.jpg"、"2023/**"
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// 跳过匹配的文件或目录（可多次指定），如 "*.png"、"Screenshots/"；
+    /// 另外遵循各目录中的 .porgignore，并自动跳过位于源目录内的输出目录
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// 自定义文件名日期正则（可多次指定，优先于内置模式），
+    /// 使用命名分组 year/month/day/hour/minute/second 或 ts/ts_ms
+    #[arg(long = "filename-pattern", value_name = "REGEX")]
+    filename_patterns: Vec<String>,
+
+    /// 文件重命名模板，如 "{date:%Y%m%d_%H%M%S}_{model}_{seq:03}{ext}"
+    /// （可用 date、subsec、make、model、stem、ext、seq；扩展名自动保留）
+    #[arg(long, value_name = "TEMPLATE")]
+    rename: Option<String>,
+
+    /// 离线 GeoNames 城市表（如 cities15000.txt），用于把 GPS 坐标解析为
+    /// {country} {region} {city}；同目录的 admin1CodesASCII.txt、countryInfo.txt 提供名称
+    #[arg(long, value_name = "FILE")]
+    geonames: Option<PathBuf>,
+
+    /// 并行读取元数据与复制文件的线程数（默认: CPU 核数）
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// 传输方式：copy、move、hardlink（硬链接）、symlink（符号链接）或 reflink（写时复制克隆）
+    #[arg(long, value_enum, default_value = "copy")]
+    mode: Mode,
+
+    /// 移动文件而非复制（等同于 --mode move）
+    #[arg(short = 'm', long, conflicts_with = "mode")]
     r#move: bool,
 
+    /// 移动需要跨设备复制时不校验副本（默认比对 SHA-256 一致后才删除源文件）
+    #[arg(long)]
+    no_verify: bool,
+
+    /// 每个文件写入磁盘后才重命名为最终文件名（更安全，但更慢）
+    #[arg(long)]
+    fsync: bool,
+
+    /// 复制（含跨设备移动）后保留的属性，逗号分隔：times（修改/访问时间）、mode（权限）、
+    /// xattrs（扩展属性）
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "LIST")]
+    preserve: Vec<Attribute>,
+
     /// 仅预览，不实际操作
     #[arg(short, long)]
     dry_run: bool,
@@ -41,91 +169,174 @@ struct Cli {
     /// 静默模式，仅输出统计结果
     #[arg(short, long)]
     quiet: bool,
+
+    /// 不按内容检测重复文件（默认跳过与本次运行或目标目录中内容相同的文件）
+    #[arg(long)]
+    no_dedup: bool,
+
+    /// 按文件内容修正错误或缺失的扩展名（如实为 HEIC 的 .jpg 输出为 .heic）
+    #[arg(long)]
+    fix_extensions: bool,
 }
 
-/// 支持的图片文件扩展名
-const SUPPORTED_EXTENSIONS: &[&str] = &[
-    "jpg", "jpeg", "png", "tiff", "tif", "heic", "heif", "cr2", "nef", "arw", "dng", "orf",
-    "rw2", "pef", "srw",
-];
-
-/// EXIF 日期时间的常见格式
-const EXIF_DATE_FORMATS: &[&str] = &[
-    "%Y:%m:%d %H:%M:%S",
-    "%Y-%m-%d %H:%M:%S",
-    "%Y/%m/%d %H:%M:%S",
-    "%Y:%m:%d %H:%M",
-    "%Y-%m-%dT%H:%M:%S",
-];
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 撤销一次整理操作：移回移动的文件，删除未被修改的副本
+    Undo(UndoArgs),
+    /// 把文件名推断、时钟校正或手动指定的日期写回 EXIF（或 XMP 附属文件）
+    FixDates(FixDatesArgs),
+    /// 监视同步文件夹（如 Syncthing），新文件写完后自动整理
+    Watch(WatchArgs),
+}
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+#[derive(Args, Debug)]
+struct FixDatesArgs {
+    /// 照片目录或单个文件（默认: 当前目录）
+    #[arg(default_value = ".")]
+    source: PathBuf,
 
-    // 验证源目录存在
-    let source = cli.source.canonicalize().unwrap_or_else(|_| cli.source.clone());
-    if !source.exists() {
-        anyhow::bail!("源目录不存在: {}", source.display());
-    }
-    if !source.is_dir() {
-        anyhow::bail!("源路径不是目录: {}", source.display());
-    }
+    /// 手动指定所有文件的拍摄时间，如 "2023-06-15 10:15" 或 "2023-06-15T10:15:00+09:00"
+    #[arg(long, value_name = "DATETIME", value_parser = date::parse_manual_date)]
+    set: Option<CaptureDate>,
 
-    // 确定输出目录
-    let output_dir = cli
-        .output
-        .clone()
-        .unwrap_or_else(|| source.join("organized"));
+    /// 自定义文件名日期正则（可多次指定）
+    #[arg(long = "filename-pattern", value_name = "REGEX")]
+    filename_patterns: Vec<String>,
 
-    let recursive = !cli.no_recursive;
+    /// 相机时钟校正 [相机=]±HH:MM[:SS]（可多次指定）
+    #[arg(long = "time-shift", value_name = "[CAMERA=]OFFSET")]
+    time_shift: Vec<ShiftRule>,
 
-    if !cli.quiet {
-        if cli.dry_run {
-            println!("🔍 预览模式 — 不会实际操作文件\n");
-        }
-        println!("📂 源目录:   {}", source.display());
-        println!("📁 输出目录: {}", output_dir.display());
-        println!(
-            "📋 操作模式: {}  |  📅 日期格式: {}  |  🔄 递归: {}",
-            if cli.r#move { "移动" } else { "复制" },
-            cli.format,
-            if recursive { "是" } else { "否" }
-        );
-        println!();
+    /// 配置文件（TOML），读取其中的 [time_shift] 表
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// 不递归扫描子目录
+    #[arg(long)]
+    no_recursive: bool,
+
+    /// 仅预览，不实际写入
+    #[arg(short, long)]
+    dry_run: bool,
+
+    /// 静默模式，仅输出统计结果
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(Args, Debug)]
+struct WatchArgs {
+    /// 监视的目录
+    dir: PathBuf,
+
+    /// 文件大小与修改时间保持不变多久后视为写完；新文件接连到达时，目录静默这么久后再整理
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = event::parse_duration)]
+    settle: TimeDelta,
+
+    /// 不使用 inotify，每隔 DURATION 扫描一次目录（网络文件系统等收不到变化通知时）
+    #[arg(long, value_name = "DURATION", value_parser = event::parse_duration)]
+    poll: Option<TimeDelta>,
+
+    /// 日志文件（默认: 输出目录/.porg/watch.log）
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+
+    /// 其余选项与整理时相同（写在以上选项之后），如 -o DIR --mode move
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, value_name = "ARGS")]
+    options: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct UndoArgs {
+    /// 要撤销的运行 ID（默认: 最近一次）
+    run_id: Option<String>,
+
+    /// 整理时使用的输出目录
+    #[arg(short, long, default_value = "organized")]
+    output: PathBuf,
+
+    /// 仅预览，不实际操作
+    #[arg(short, long)]
+    dry_run: bool,
+
+    /// 静默模式，仅输出统计结果
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+fn main() -> Result<()> {
+    let (cli, config) = parse_cli()?;
+
+    match &cli.command {
+        Some(Command::Undo(args)) => return run_undo(args),
+        Some(Command::FixDates(args)) => return run_fix_dates(args),
+        Some(Command::Watch(args)) => return run_watch(args),
+        None => {}
     }
 
-    // 收集所有照片文件
-    let photos = collect_photos(&source, recursive)?;
+    let Session {
+        source,
+        mut filter,
+        mut organizer,
+    } = start_session(&cli, &config)?;
+
+    // 收集所有照片和视频文件
+    let mut groups = collect_photos(&source, !cli.no_recursive, &mut filter, cli.fix_extensions)?;
+    pair_live_photos(&mut groups, cli.quiet);
+    let photo_count: usize = groups.iter().map(|g| g.items.len()).sum();
 
     if !cli.quiet {
-        println!("📸 找到 {} 张照片\n", photos.len());
+        println!("📸 找到 {} 张照片\n", photo_count);
     }
 
-    if photos.is_empty() {
+    if groups.is_empty() {
         println!("没有找到支持的照片文件。");
         return Ok(());
     }
 
     // 处理每张照片
-    let mut stats = Stats::default();
-
-    for photo_path in &photos {
-        match process_photo(photo_path, &output_dir, &cli, &mut stats) {
-            Ok(()) => {}
-            Err(e) => {
-                stats.errors += 1;
-                eprintln!("⚠️  处理失败: {} — {}", photo_path.display(), e);
-            }
-        }
-    }
+    organizer.run(&groups);
+    let stats = &organizer.stats;
+    let journal = &organizer.journal;
 
     // 输出统计
     println!();
     println!("═══════════════════════════════════════");
     println!("📊 处理完成:");
-    println!("   ✅ 已分类  {} 张  📁 未分类  {} 张  ⏭ 跳过  {} 张  ❌ 错误  {} 张",
-        stats.organized, stats.unsorted, stats.skipped, stats.errors);
+    println!("   ✅ 已分类  {} 张  📁 未分类  {} 张  🔁 重复  {} 张  ⏭ 跳过  {} 张  ❌ 错误  {} 张",
+        stats.organized, stats.unsorted, stats.duplicates, stats.skipped, stats.errors);
+    if stats.sidecars > 0 {
+        println!("   📎 附属文件 {} 个", stats.sidecars);
+    }
+    if !stats.skipped_sidecars.is_empty() {
+        println!("   ⚠️  {} 个附属文件随重复文件跳过，未处理", stats.skipped_sidecars.len());
+    }
+    if stats.fixed_extensions > 0 {
+        println!("   🔧 修正扩展名 {} 个", stats.fixed_extensions);
+    }
+    if stats.reflink_fallbacks > 0 {
+        println!("   ⚠️  文件系统不支持克隆，{} 个文件改为复制", stats.reflink_fallbacks);
+    }
+    if stats.xattrs_unsupported > 0 {
+        println!("   ⚠️  目标文件系统不支持扩展属性，{} 个文件未保留 xattrs", stats.xattrs_unsupported);
+    }
+    if cli.group_by == GroupBy::Event {
+        println!("   🗂 事件 {} 个", stats.events);
+    }
+    if stats.source_counts.keys().any(|s| *s != DateSource::Exif) {
+        let parts: Vec<String> = stats
+            .source_counts
+            .iter()
+            .map(|(source, count)| format!("{} {} 张", source, count))
+            .collect();
+        println!("   🧭 日期来源: {}", parts.join("  "));
+    }
     println!("═══════════════════════════════════════");
 
+    if !journal.is_empty() {
+        println!("🧾 运行 ID: {}（撤销: porg undo {}）", journal.run_id(), journal.run_id());
+    }
+
     // 输出日期分类统计
     if !cli.quiet && !stats.date_counts.is_empty() {
         println!("\n📅 日期分布:");
@@ -136,190 +347,312 @@ fn main() -> Result<()> {
         }
     }
 
-    Ok(())
-}
-
-/// 收集目录中所有支持格式的照片文件
-fn collect_photos(source: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
-    let walker = if recursive {
-        WalkDir::new(source)
-    } else {
-        WalkDir::new(source).max_depth(1)
-    };
-
-    let mut photos: Vec<PathBuf> = Vec::new();
-
-    for entry in walker.into_iter().filter_map(|e| e.ok()) {
-        let path = entry.path();
-        if path.is_file() && is_supported_image(path) {
-            photos.push(path.to_path_buf());
+    // 输出地点统计
+    if !cli.quiet && !stats.place_counts.is_empty() {
+        println!("\n📍 地点分布:");
+        let mut places: Vec<_> = stats.place_counts.iter().collect();
+        places.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        for (place, count) in places {
+            println!("   {} — {} 张", place, count);
         }
     }
 
-    photos.sort();
-    Ok(photos)
+    Ok(())
 }
 
-/// 判断文件是否是支持的图片格式
-fn is_supported_image(path: &Path) -> bool {
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
-        .unwrap_or(false)
+/// 一次整理（或监视）的源目录、筛选条件与整理器
+struct Session<'a> {
+    source: PathBuf,
+    filter: Filter,
+    organizer: Organizer<'a>,
 }
 
-/// 从 EXIF 元信息提取拍照日期
-fn extract_capture_date(path: &Path) -> Result<Option<NaiveDateTime>> {
-    let file = fs::File::open(path).context("无法打开文件")?;
-    let mut buf_reader = BufReader::new(file);
+/// 校验源目录与输出目录，创建整理器，输出运行设置并清理遗留的临时文件
+fn start_session<'a>(cli: &'a Cli, config: &Config) -> Result<Session<'a>> {
+    // 验证源目录存在
+    let source = cli.source.canonicalize().unwrap_or_else(|_| cli.source.clone());
+    if !source.exists() {
+        anyhow::bail!("源目录不存在: {}", source.display());
+    }
+    if !source.is_dir() {
+        anyhow::bail!("源路径不是目录: {}", source.display());
+    }
 
-    let exif = match Reader::new().read_from_container(&mut buf_reader) {
-        Ok(exif) => exif,
-        Err(_) => return Ok(None),
-    };
+    // 确定输出目录（使用绝对路径，操作日志才能在任意位置撤销）
+    let output_dir = cli
+        .output
+        .clone()
+        .unwrap_or_else(|| source.join("organized"));
+    let output_dir = std::path::absolute(&output_dir).unwrap_or(output_dir);
 
-    // 按优先级尝试不同的日期字段
-    let date_tags = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime];
+    let recursive = !cli.no_recursive;
+    let mut filter = Filter::new(&source, &cli.include, &cli.exclude)?;
+    // 输出目录在源目录内时不扫描，避免再次整理已整理好的照片
+    let output_real = output_dir.canonicalize().unwrap_or_else(|_| output_dir.clone());
+    let output_inside = output_real.starts_with(&source) && output_real != source;
+    if output_inside {
+        filter.skip_dir(output_real);
+    }
+    let organizer = Organizer::new(cli, source.clone(), output_dir.clone())?;
 
-    for tag in &date_tags {
-        if let Some(field) = exif.get_field(*tag, In::PRIMARY) {
-            let date_str = field.display_value().to_string();
-            if let Some(dt) = parse_exif_date(&date_str) {
-                return Ok(Some(dt));
+    if !cli.quiet {
+        if cli.dry_run {
+            println!("🔍 预览模式 — 不会实际操作文件\n");
+        }
+        if !config.paths.is_empty() {
+            match &cli.profile {
+                Some(profile) => {
+                    println!("⚙️  配置文件: {}（配置: {}）", config.describe_paths(), profile)
+                }
+                None => println!("⚙️  配置文件: {}", config.describe_paths()),
             }
         }
+        println!("📂 源目录:   {}", source.display());
+        if output_inside {
+            println!("📁 输出目录: {}（扫描时跳过）", output_dir.display());
+        } else {
+            println!("📁 输出目录: {}", output_dir.display());
+        }
+        println!(
+            "📋 操作模式: {}  |  📅 目录模板: {}  |  🔄 递归: {}  |  🧵 线程: {}",
+            organizer.transfer.mode.describe(),
+            cli.format,
+            if recursive { "是" } else { "否" },
+            organizer.jobs
+        );
+        if organizer.transfer.preserve != Preserve::default() {
+            println!("🏷  保留属性: {}", organizer.transfer.preserve.describe());
+        }
+        if organizer.transfer.mode == Mode::Move && !organizer.transfer.verify {
+            println!("⚠️  已关闭移动校验：跨设备复制后直接删除源文件");
+        }
+        if !cli.include.is_empty() {
+            println!("🔎 只包含: {}", cli.include.join("  "));
+        }
+        if !cli.exclude.is_empty() {
+            println!("🚫 排除: {}", cli.exclude.join("  "));
+        }
+        if cli.fix_extensions {
+            println!("🔧 按文件内容修正扩展名");
+        }
+        if cli.fallback != [DateSource::Exif, DateSource::Filename] {
+            let sources: Vec<String> = cli.fallback.iter().map(|s| s.to_string()).collect();
+            println!("🧭 日期来源: {}", sources.join(" → "));
+        }
+        if cli.tz != TzMode::Capture {
+            println!("🌐 归档时区: {}", cli.tz);
+        }
+        if !cli.time_shift.is_empty() {
+            println!("⏱  时钟校正: {} 条规则", cli.time_shift.len());
+        }
+        if cli.group_by == GroupBy::Event {
+            println!("🗂  按事件分组: 间隔超过 {} 视为新事件", event::format_duration(cli.event_gap));
+        }
+        if let Some(geocoder) = &organizer.geocoder {
+            println!("🗺  地理数据: {} 个地点", geocoder.len());
+        }
+        println!();
     }
 
-    Ok(None)
+    // 清理上次中断时遗留的临时文件
+    if !cli.dry_run {
+        let removed = transfer::remove_stale_temps(&output_dir);
+        if removed > 0 && !cli.quiet {
+            println!("🧹 清理了 {} 个中断遗留的临时文件\n", removed);
+        }
+    }
+
+    Ok(Session {
+        source,
+        filter,
+        organizer,
+    })
 }
 
-/// 尝试多种格式解析 EXIF 日期字符串
-fn parse_exif_date(date_str: &str) -> Option<NaiveDateTime> {
-    let trimmed = date_str.trim().trim_matches('"');
-    for fmt in EXIF_DATE_FORMATS {
-        if let Ok(dt) = NaiveDateTime::parse_from_str(trimmed, fmt) {
-            return Some(dt);
-        }
+/// 解析命令行，并用配置文件补全命令行中未指定的选项
+fn parse_cli() -> Result<(Cli, Config)> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    // 子命令使用各自的选项
+    if cli.command.is_some() {
+        return Ok((cli, Config::default()));
     }
-    None
+    with_config(cli, &matches, std::env::args_os().collect())
 }
 
-/// 处理单张照片：提取日期，复制/移动到目标目录
-fn process_photo(photo_path: &Path, output_dir: &Path, cli: &Cli, stats: &mut Stats) -> Result<()> {
-    let capture_date = extract_capture_date(photo_path)?;
-
-    let target_subdir = match &capture_date {
-        Some(dt) => {
-            let date_dir = dt.format(&cli.format).to_string();
-            *stats
-                .date_counts
-                .entry(dt.format("%Y-%m-%d").to_string())
-                .or_insert(0) += 1;
-            output_dir.join(date_dir)
-        }
-        None => {
-            stats.unsorted += 1;
-            output_dir.join("unsorted")
-        }
+/// 用配置文件补全命令行 `argv`（`matches` 为其解析结果）中未指定的选项
+fn with_config(cli: Cli, matches: &ArgMatches, argv: Vec<OsString>) -> Result<(Cli, Config)> {
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::discover(&cli.source)?,
     };
+    let (args, from_config) = config
+        .settings(cli.profile.as_deref())
+        .and_then(|settings| config::to_args(&settings, &Cli::command(), matches))
+        .with_context(|| format!("配置文件有误: {}", config.describe_paths()))?;
 
-    // 确定目标文件路径（处理文件名冲突）
-    let file_name = photo_path
-        .file_name()
-        .context("无法获取文件名")?
-        .to_string_lossy()
-        .to_string();
-
-    let target_path = resolve_conflict(&target_subdir, &file_name);
+    let (cli, merged) = if args.is_empty() {
+        (cli, matches.clone())
+    } else {
+        let argv = std::iter::once(OsString::from("porg"))
+            .chain(args)
+            .chain(argv.into_iter().skip(1));
+        let merged = Cli::command().try_get_matches_from(argv).map_err(|e| {
+            anyhow::anyhow!("配置文件中的选项无效: {}\n{}", config.describe_paths(), e)
+        })?;
+        (Cli::from_arg_matches(&merged)?, merged)
+    };
 
-    // 目标已存在则跳过
-    if target_path.exists() {
-        stats.skipped += 1;
-        return Ok(());
+    if cli.print_config {
+        if !config.paths.is_empty() {
+            println!("# 配置文件: {}", config.describe_paths());
+        }
+        let effective = config::render_effective(&Cli::command(), matches, &merged, &from_config);
+        print!("{}", effective);
+        std::process::exit(0);
     }
+    Ok((cli, config))
+}
 
-    let action = if cli.r#move { "移动" } else { "复制" };
-    let date_info = capture_date
-        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
-        .unwrap_or_else(|| "无日期".to_string());
+/// 执行 `porg undo`
+fn run_undo(args: &UndoArgs) -> Result<()> {
+    let output_dir = std::path::absolute(&args.output).unwrap_or_else(|_| args.output.clone());
+    let stats = undo::undo(&output_dir, args.run_id.as_deref(), args.dry_run, args.quiet)?;
 
-    if !cli.quiet {
-        println!(
-            "  {} {} → {} [{}]",
-            if cli.dry_run {
-                format!("[预览{}]", action)
-            } else {
-                format!("{}:", action)
-            },
-            photo_path.display(),
-            target_path.display(),
-            date_info
-        );
+    println!();
+    println!("═══════════════════════════════════════");
+    println!("↩️  撤销完成:");
+    println!("   📦 已还原  {} 张  🗑 已删除  {} 张  📌 保留  {} 张  ❌ 错误  {} 张",
+        stats.restored, stats.deleted, stats.kept, stats.errors);
+    println!("═══════════════════════════════════════");
+
+    Ok(())
+}
+
+/// 执行 `porg fix-dates`
+fn run_fix_dates(args: &FixDatesArgs) -> Result<()> {
+    if !args.source.exists() {
+        anyhow::bail!("源路径不存在: {}", args.source.display());
     }
+    let config = args.config.as_deref().map(Config::load).transpose()?.unwrap_or_default();
+    let shifts = config.time_shift()?.into_iter().chain(args.time_shift.iter().cloned());
+    let options = fixdates::FixOptions {
+        dates: DateExtractor::new(&[DateSource::Exif, DateSource::Filename], &args.filename_patterns)?,
+        shifts: TimeShifts::new(shifts),
+        manual: args.set,
+        dry_run: args.dry_run,
+        quiet: args.quiet,
+    };
 
-    if !cli.dry_run {
-        fs::create_dir_all(&target_subdir)
-            .with_context(|| format!("无法创建目录: {}", target_subdir.display()))?;
-
-        if cli.r#move {
-            if fs::rename(photo_path, &target_path).is_err() {
-                fs::copy(photo_path, &target_path).with_context(|| {
-                    format!("无法复制: {} → {}", photo_path.display(), target_path.display())
-                })?;
-                fs::remove_file(photo_path)
-                    .with_context(|| format!("无法删除源文件: {}", photo_path.display()))?;
-            }
-        } else {
-            fs::copy(photo_path, &target_path).with_context(|| {
-                format!("无法复制: {} → {}", photo_path.display(), target_path.display())
-            })?;
-        }
+    if !args.quiet && args.dry_run {
+        println!("🔍 预览模式 — 不会实际写入文件\n");
     }
+    let mut filter = Filter::new(&args.source, &[], &[])?;
+    let mut groups = collect_photos(&args.source, !args.no_recursive, &mut filter, false)?;
+    pair_live_photos(&mut groups, true);
+    let stats = fixdates::fix_dates(&groups, &options);
 
-    if capture_date.is_some() {
-        stats.organized += 1;
+    println!();
+    println!("═══════════════════════════════════════");
+    println!("🛠  写回完成:");
+    println!("   📝 EXIF  {} 张  📎 XMP  {} 张  ✔ 无需修改  {} 张  ❓ 无日期  {} 张  ❌ 错误  {} 张",
+        stats.exif, stats.xmp, stats.unchanged, stats.undated, stats.errors);
+    println!("═══════════════════════════════════════");
+    if stats.exif + stats.xmp > 0 && !options.shifts.is_empty() && args.set.is_none() {
+        println!("💡 日期已包含时钟校正，之后整理这些文件时请去掉对应的 --time-shift 规则");
     }
 
     Ok(())
 }
 
-/// 解决文件名冲突：如果目标已存在，追加 _1, _2, ... 后缀
-fn resolve_conflict(dir: &Path, file_name: &str) -> PathBuf {
-    let target = dir.join(file_name);
-    if !target.exists() {
-        return target;
+/// 执行 `porg watch`
+fn run_watch(args: &WatchArgs) -> Result<()> {
+    // 整理选项按普通整理的命令行解析，同样读取配置文件
+    let argv: Vec<OsString> = std::iter::once(OsString::from("porg"))
+        .chain(args.options.iter().cloned())
+        .chain(std::iter::once(args.dir.clone().into_os_string()))
+        .collect();
+    let matches = Cli::command().try_get_matches_from(&argv).unwrap_or_else(|e| e.exit());
+    let cli = Cli::from_arg_matches(&matches)?;
+    let (cli, config) = with_config(cli, &matches, argv)?;
+    if cli.dry_run {
+        anyhow::bail!("监视模式不支持 --dry-run");
     }
 
-    let stem = Path::new(file_name)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or(file_name);
-    let ext = Path::new(file_name)
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("");
-
-    for i in 1..10000 {
-        let new_name = if ext.is_empty() {
-            format!("{}_{}", stem, i)
-        } else {
-            format!("{}_{}.{}", stem, i, ext)
-        };
-        let new_target = dir.join(&new_name);
-        if !new_target.exists() {
-            return new_target;
+    let Session {
+        source,
+        filter,
+        mut organizer,
+    } = start_session(&cli, &config)?;
+    let options = watch::WatchOptions {
+        settle: args.settle.to_std()?,
+        poll: args.poll.map(|d| d.to_std()).transpose()?,
+        log: args
+            .log
+            .clone()
+            .unwrap_or_else(|| organizer.output_dir().join(watch::LOG_FILE)),
+        recursive: !cli.no_recursive,
+        fix_extensions: cli.fix_extensions,
+        quiet: cli.quiet,
+    };
+    watch::watch(&source, filter, &mut organizer, &options)
+}
+
+/// 收集目录中所有支持格式的照片和视频文件，关联附属文件并按文件名主干分组
+fn collect_photos(
+    source: &Path,
+    recursive: bool,
+    filter: &mut Filter,
+    fix_extensions: bool,
+) -> Result<Vec<MediaGroup>> {
+    let walker = if recursive {
+        WalkDir::new(source)
+    } else {
+        WalkDir::new(source).max_depth(1)
+    };
+
+    let files: Vec<PathBuf> = walker
+        .into_iter()
+        .filter_entry(|e| filter.allows(e.path(), e.file_type().is_dir()))
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .map(|e| e.into_path())
+        .collect();
+
+    Ok(group_files(files, filter, fix_extensions))
+}
+
+/// 从文件中挑出照片、视频与附属文件，关联附属文件并按文件名主干分组
+fn group_files(files: Vec<PathBuf>, filter: &Filter, fix_extensions: bool) -> Vec<MediaGroup> {
+    let mut photos: Vec<PathBuf> = Vec::new();
+    let mut sidecars: Vec<PathBuf> = Vec::new();
+
+    for path in files {
+        if media::is_supported(&path) {
+            photos.push(path);
+        } else if sidecar::is_sidecar(&path) {
+            sidecars.push(path);
+        } else if !media::has_extension(&path, media::IGNORED_EXTENSIONS)
+            && sniff::detect(&path).is_some()
+        {
+            // 扩展名错误或缺失（如聊天软件导出的文件）时按文件头识别
+            photos.push(path);
         }
     }
 
-    dir.join(format!("{}_{}", file_name, chrono::Utc::now().timestamp()))
+    // 附属文件不受 --include 限制，随主文件一起处理
+    photos.retain(|path| filter.includes(path));
+    photos.sort();
+    sidecars.sort();
+    let matched = sidecar::match_sidecars(&photos, sidecars);
+
+    group::group_by_stem(photos, matched, fix_extensions)
 }
 
-/// 统计信息
-#[derive(Default)]
-struct Stats {
-    organized: usize,
-    unsorted: usize,
-    skipped: usize,
-    errors: usize,
-    date_counts: HashMap<String, usize>,
+/// 收集文件后配对 Live Photo，并在非静默模式下报告
+fn pair_live_photos(groups: &mut Vec<MediaGroup>, quiet: bool) {
+    let paired = livephoto::pair(groups);
+    if !quiet && paired > 0 {
+        println!("🎞  配对 Live Photo {} 组", paired);
+    }
 }
@@ -0,0 +1,34 @@
+//! 按扩展名判断照片、RAW 与视频格式
+
+use crate::video;
+use std::path::Path;
+
+/// 支持的图片文件扩展名
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "tiff", "tif", "heic", "heif", "avif", "webp", "cr2", "nef", "arw",
+    "dng", "orf", "rw2", "pef", "srw",
+];
+
+/// 内容是图片、但不作为照片整理的扩展名（相机为视频生成的缩略图）
+pub const IGNORED_EXTENSIONS: &[&str] = &["thm"];
+
+/// RAW 格式扩展名
+pub const RAW_EXTENSIONS: &[&str] = &["cr2", "nef", "arw", "dng", "orf", "rw2", "pef", "srw"];
+
+/// 判断文件的扩展名是否是支持的图片或视频格式
+pub fn is_supported(path: &Path) -> bool {
+    has_extension(path, IMAGE_EXTENSIONS) || video::has_video_extension(path)
+}
+
+/// 判断文件是否是 RAW 格式
+pub fn is_raw(path: &Path) -> bool {
+    has_extension(path, RAW_EXTENSIONS)
+}
+
+/// 扩展名（不区分大小写）是否在列表中
+pub fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| extensions.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
@@ -0,0 +1,178 @@
+//! 相机元数据（用于文件名与目录模板）
+
+use crate::group::MediaGroup;
+use crate::video;
+use exif::{Exif, In, Reader, Tag, Value};
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// 在媒体文件开头查找内嵌 XMP 的字节数
+const XMP_SCAN_LIMIT: u64 = 1024 * 1024;
+
+/// QuickTime 中 ISO 6709 格式的拍摄地点
+const APPLE_LOCATION_KEY: &str = "com.apple.quicktime.location.ISO6709";
+
+/// 从 EXIF / XMP 读取的相机与地点信息
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// 机身序列号
+    pub serial: Option<String>,
+    /// GPS 坐标（纬度, 经度）
+    pub gps: Option<(f64, f64)>,
+    pub country: Option<String>,
+    pub region: Option<String>,
+    pub city: Option<String>,
+}
+
+impl Metadata {
+    /// 读取文件的 EXIF 元数据；读取失败时返回空值
+    pub fn read(path: &Path) -> Metadata {
+        if video::is_video(path) {
+            let location = video::read_apple_metadata(path, APPLE_LOCATION_KEY).ok().flatten();
+            return Metadata {
+                gps: location.as_deref().and_then(parse_iso6709),
+                ..Metadata::default()
+            };
+        }
+        let Ok(file) = fs::File::open(path) else {
+            return Metadata::default();
+        };
+        let Ok(exif) = Reader::new().read_from_container(&mut BufReader::new(file)) else {
+            return Metadata::default();
+        };
+        Metadata {
+            make: ascii_field(&exif, Tag::Make),
+            model: ascii_field(&exif, Tag::Model),
+            lens: ascii_field(&exif, Tag::LensModel),
+            serial: ascii_field(&exif, Tag::BodySerialNumber),
+            gps: gps_coordinates(&exif),
+            ..Metadata::default()
+        }
+    }
+
+    /// 读取一组文件的元数据：各字段取组内第一个有值的成员，
+    /// 国家/地区/城市优先取 XMP 附属文件，其次取文件内嵌的 XMP
+    pub fn for_group(group: &MediaGroup) -> Metadata {
+        let mut merged = Metadata::default();
+        for item in &group.items {
+            let m = Metadata::read(&item.path);
+            merged.make = merged.make.or(m.make);
+            merged.model = merged.model.or(m.model);
+            merged.lens = merged.lens.or(m.lens);
+            merged.serial = merged.serial.or(m.serial);
+            merged.gps = merged.gps.or(m.gps);
+        }
+
+        let sidecars = group
+            .items
+            .iter()
+            .flat_map(|item| &item.sidecars)
+            .filter(|sc| sc.path.extension().is_some_and(|e| e.eq_ignore_ascii_case("xmp")))
+            .map(|sc| sc.path.as_path());
+        let media = group.items.iter().map(|item| item.path.as_path());
+        if let Some(xmp) = sidecars.chain(media).find_map(read_xmp) {
+            merged.country = xmp_field(&xmp, "photoshop:Country");
+            merged.region = xmp_field(&xmp, "photoshop:State");
+            merged.city = xmp_field(&xmp, "photoshop:City");
+        }
+        merged
+    }
+}
+
+/// 读取 ASCII 类型的 EXIF 字段
+pub fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let text = String::from_utf8_lossy(values.first()?);
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// 读取包含地点信息的 XMP 文本（附属文件或媒体文件开头内嵌的 XMP）
+fn read_xmp(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let mut data = Vec::new();
+    file.take(XMP_SCAN_LIMIT).read_to_end(&mut data).ok()?;
+    let text = String::from_utf8_lossy(&data);
+    text.contains("photoshop:").then(|| text.into_owned())
+}
+
+/// 读取 XMP 属性（属性或元素形式均可）
+fn xmp_field(xmp: &str, name: &str) -> Option<String> {
+    let mut rest = xmp;
+    while let Some(pos) = rest.find(name) {
+        rest = &rest[pos + name.len()..];
+        let value = if let Some(attr) = rest.trim_start().strip_prefix('=') {
+            let attr = attr.trim_start();
+            let quote = attr.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            attr[1..].split(quote).next()
+        } else if let Some(elem) = rest.strip_prefix('>') {
+            elem.split('<').next()
+        } else {
+            None
+        };
+        if let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) {
+            return Some(unescape_xml(value));
+        }
+    }
+    None
+}
+
+/// 读取 EXIF GPS 坐标（度分秒 + N/S/E/W 参考方向）
+fn gps_coordinates(exif: &Exif) -> Option<(f64, f64)> {
+    let degrees = |tag: Tag, ref_tag: Tag, negative: u8| {
+        let Value::Rational(dms) = &exif.get_field(tag, In::PRIMARY)?.value else {
+            return None;
+        };
+        if dms.len() < 3 || dms.iter().any(|r| r.denom == 0) {
+            return None;
+        }
+        let value = dms[0].to_f64() + dms[1].to_f64() / 60.0 + dms[2].to_f64() / 3600.0;
+        let Value::Ascii(r) = &exif.get_field(ref_tag, In::PRIMARY)?.value else {
+            return None;
+        };
+        let sign = match r.first()?.first()? {
+            c if *c == negative => -1.0,
+            _ => 1.0,
+        };
+        Some(sign * value)
+    };
+    let latitude = degrees(Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?;
+    let longitude = degrees(Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?;
+    valid_coordinates(latitude, longitude)
+}
+
+/// 解析 ISO 6709 坐标，如 "+35.6895+139.6917+040.000/"
+fn parse_iso6709(s: &str) -> Option<(f64, f64)> {
+    let s = s.trim().trim_end_matches('/');
+    let lon_start = s.get(1..)?.find(['+', '-'])? + 1;
+    let lon_end = s[lon_start + 1..]
+        .find(['+', '-'])
+        .map_or(s.len(), |i| i + lon_start + 1);
+    let latitude = s[..lon_start].parse().ok()?;
+    let longitude = s[lon_start..lon_end].parse().ok()?;
+    valid_coordinates(latitude, longitude)
+}
+
+/// 排除超出范围的坐标及常见的 (0, 0) 占位值
+fn valid_coordinates(latitude: f64, longitude: f64) -> Option<(f64, f64)> {
+    let valid = (-90.0..=90.0).contains(&latitude)
+        && (-180.0..=180.0).contains(&longitude)
+        && (latitude, longitude) != (0.0, 0.0);
+    valid.then_some((latitude, longitude))
+}
+
+/// 还原 XML 预定义实体
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
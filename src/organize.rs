@@ -0,0 +1,599 @@
+//! 整理流程：确定每组文件的目标位置，复制/移动并记录
+//!
+//! 读取元数据与复制文件由多个线程并行完成；目标路径（去重、重命名、冲突后缀）
+//! 按输入顺序在单线程中确定，因此重复运行得到相同的目录结构。
+
+use crate::date::{CaptureDate, DateExtractor, DateSource};
+use crate::dedup::DedupIndex;
+use crate::event::{self, EventLabel, GroupBy};
+use crate::geocode::{Geocoder, Place};
+use crate::group::MediaGroup;
+use crate::hash::{self, Digest};
+use crate::journal::{Journal, Operation};
+use crate::layout::{DirContext, DirLayout};
+use crate::metadata::Metadata;
+use crate::pool;
+use crate::preserve::Preserve;
+use crate::rename::{RenameContext, Renamer};
+use crate::template::sanitize;
+use crate::timeshift::{TimeShift, TimeShifts};
+use crate::transfer::{self, Mode, TransferOptions, Transferred};
+use crate::Cli;
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// 统计信息
+#[derive(Default)]
+pub struct Stats {
+    pub organized: usize,
+    pub unsorted: usize,
+    pub skipped: usize,
+    pub duplicates: usize,
+    pub errors: usize,
+    pub sidecars: usize,
+    pub date_counts: HashMap<String, usize>,
+    pub source_counts: BTreeMap<DateSource, usize>,
+    pub place_counts: HashMap<String, usize>,
+    pub events: usize,
+    /// 文件系统不支持克隆、改为复制的文件数
+    pub reflink_fallbacks: usize,
+    /// 按内容修正了扩展名的文件数
+    pub fixed_extensions: usize,
+    /// 目标文件系统不支持、未能保留扩展属性的文件数
+    pub xattrs_unsupported: usize,
+    /// 随重复文件跳过、未被处理的附属文件
+    pub skipped_sidecars: Vec<PathBuf>,
+}
+
+impl Stats {
+    /// 计入一个已完成（预览模式下为计划中）的文件
+    fn record(&mut self, job: &Job) {
+        match &job.filing_date {
+            Some(date) => *self.date_counts.entry(date.clone()).or_insert(0) += 1,
+            None => self.unsorted += 1,
+        }
+        self.sidecars += job.sidecars.len();
+        self.fixed_extensions += usize::from(job.extension_fixed);
+        if let Some(source) = job.date_source {
+            self.organized += 1;
+            *self.source_counts.entry(source).or_insert(0) += 1;
+        }
+        if let Some(place) = &job.place {
+            *self.place_counts.entry(place.clone()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// 第一遍分析得到的组信息
+struct Plan<'g> {
+    group: &'g MediaGroup,
+    extracted: Option<(CaptureDate, DateSource)>,
+    filing_time: Option<NaiveDateTime>,
+    /// 已应用的相机时钟校正
+    shift: Option<TimeShift>,
+    metadata: Metadata,
+    place: Option<Place>,
+}
+
+impl Plan<'_> {
+    /// 事件标签候选值
+    fn label(&self, label: EventLabel) -> Option<&str> {
+        match label {
+            EventLabel::City => self.metadata.city.as_deref(),
+            EventLabel::Region => self.metadata.region.as_deref(),
+            EventLabel::Country => self.metadata.country.as_deref(),
+        }
+    }
+}
+
+/// 组的归档目录时间及所属事件（下标, 标签）
+struct Folder {
+    time: NaiveDateTime,
+    event: Option<(usize, Option<String>)>,
+}
+
+/// 已建立的事件：监视模式下跨批保留，后到的文件与之相隔不超过间隔时归入同一目录
+struct KnownEvent {
+    /// 归档目录所用的时间（建立时最早的拍摄时间）
+    start: NaiveDateTime,
+    /// 目前最早与最晚的拍摄时间
+    first: NaiveDateTime,
+    last: NaiveDateTime,
+    label: Option<String>,
+}
+
+/// 第二遍确定的一次文件传输（连同附属文件）
+struct Job {
+    source: PathBuf,
+    target: PathBuf,
+    digest: Option<Digest>,
+    /// 附属文件 (源路径, 目标路径)
+    sidecars: Vec<(PathBuf, PathBuf)>,
+    /// 以下为成功后计入统计的信息
+    filing_date: Option<String>,
+    date_source: Option<DateSource>,
+    place: Option<String>,
+    extension_fixed: bool,
+}
+
+/// 一次整理运行的状态
+pub struct Organizer<'a> {
+    cli: &'a Cli,
+    /// 传输方式（`--move` 等同于 `--mode move`）与校验设置
+    pub transfer: TransferOptions,
+    source_dir: PathBuf,
+    output_dir: PathBuf,
+    layout: DirLayout,
+    dates: DateExtractor,
+    renamer: Option<Renamer>,
+    pub geocoder: Option<Geocoder>,
+    /// 工作线程数
+    pub jobs: usize,
+    shifts: TimeShifts,
+    dedup: Option<DedupIndex>,
+    /// 已建立的事件，下标即事件编号
+    events: Vec<KnownEvent>,
+    /// 事件目录 → 占用它的事件编号
+    event_dirs: HashMap<PathBuf, usize>,
+    /// 本次运行已分配（但可能尚未写入）的目标路径
+    reserved: HashSet<PathBuf>,
+    pub journal: Journal,
+    pub stats: Stats,
+    /// 本次运行中处理失败的文件及原因
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl<'a> Organizer<'a> {
+    pub fn new(cli: &'a Cli, source_dir: PathBuf, output_dir: PathBuf) -> Result<Self> {
+        Ok(Organizer {
+            cli,
+            transfer: TransferOptions {
+                mode: if cli.r#move { Mode::Move } else { cli.mode },
+                verify: !cli.no_verify,
+                fsync: cli.fsync,
+                preserve: Preserve::new(&cli.preserve),
+            },
+            source_dir,
+            layout: DirLayout::new(&cli.format)?,
+            dates: DateExtractor::new(&cli.fallback, &cli.filename_patterns)?,
+            renamer: cli.rename.as_deref().map(Renamer::new).transpose()?,
+            geocoder: cli.geonames.as_deref().map(Geocoder::load).transpose()?,
+            jobs: cli.jobs.map_or_else(pool::default_jobs, |n| n.get()),
+            // 配置文件中的规则排在命令行规则之前，被同级的命令行规则覆盖
+            shifts: TimeShifts::new(cli.time_shift.iter().cloned()),
+            dedup: (!cli.no_dedup).then(DedupIndex::default),
+            events: Vec::new(),
+            event_dirs: HashMap::new(),
+            reserved: HashSet::new(),
+            journal: Journal::new(&output_dir),
+            output_dir,
+            stats: Stats::default(),
+            failures: Vec::new(),
+        })
+    }
+
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// 开始新的一次运行（监视模式的每一批）：新的操作日志，清空统计与本次运行的状态；
+    /// 保留已建立的事件，使分批到达的同一事件归入同一目录
+    pub fn next_run(&mut self) {
+        self.journal = Journal::new(&self.output_dir);
+        self.stats = Stats::default();
+        self.failures.clear();
+        self.reserved.clear();
+        // 两批之间整理目录可能被改动，重新建立查重索引
+        self.dedup = (!self.cli.no_dedup).then(DedupIndex::default);
+    }
+
+    /// 分三遍处理所有组：并行提取日期与元数据，依次确定目标路径，再并行复制/移动
+    pub fn run(&mut self, groups: &[MediaGroup]) {
+        let this = &*self;
+        let results = pool::map(self.jobs, groups, |group| this.plan(group));
+        let mut plans = Vec::new();
+        for (group, result) in groups.iter().zip(results) {
+            match result {
+                Ok(plan) => plans.push(plan),
+                Err(e) => self.fail(&group.items[0].path, e),
+            }
+        }
+
+        // 每组归档目录所用的时间：按天时为自己的拍摄时间，按事件时为所属事件的开始时间
+        let folders: Vec<Option<Folder>> = match self.cli.group_by {
+            GroupBy::Day => plans
+                .iter()
+                .map(|plan| plan.filing_time.map(|time| Folder { time, event: None }))
+                .collect(),
+            GroupBy::Event => {
+                let times: Vec<_> = plans.iter().map(|plan| plan.filing_time).collect();
+                let (events, membership) = event::cluster(&times, self.cli.event_gap);
+                self.stats.events = events.len();
+                let ids: Vec<usize> = (0..events.len())
+                    .map(|index| {
+                        let members = plans
+                            .iter()
+                            .zip(&membership)
+                            .filter(|(_, m)| **m == Some(index))
+                            .map(|(plan, _)| plan);
+                        self.known_event(events[index].start, members)
+                    })
+                    .collect();
+                membership
+                    .iter()
+                    .map(|m| {
+                        m.map(|i| {
+                            let event = &self.events[ids[i]];
+                            Folder {
+                                time: event.start,
+                                event: Some((ids[i], event.label.clone())),
+                            }
+                        })
+                    })
+                    .collect()
+            }
+        };
+
+        let mut jobs = Vec::new();
+        for (plan, folder) in plans.iter().zip(folders) {
+            match self.prepare(plan, folder) {
+                Ok(group_jobs) => jobs.extend(group_jobs),
+                Err(e) => self.fail(&plan.group.items[0].path, e),
+            }
+        }
+
+        if self.cli.dry_run {
+            for job in &jobs {
+                self.stats.record(job);
+            }
+            return;
+        }
+
+        let options = self.transfer;
+        let journal = Mutex::new(&mut self.journal);
+        let results = pool::map(self.jobs, &jobs, |job| execute(&options, &journal, job));
+        for (job, result) in jobs.iter().zip(results) {
+            match result {
+                Ok(transferred) => {
+                    self.stats.record(job);
+                    for file in transferred {
+                        if options.mode == Mode::Reflink && file.operation != Operation::Reflink {
+                            self.stats.reflink_fallbacks += 1;
+                        }
+                        self.stats.xattrs_unsupported += usize::from(file.xattrs_unsupported);
+                    }
+                }
+                Err(e) => self.fail(&job.source, e),
+            }
+        }
+    }
+
+    /// 记录一个文件的处理失败并继续
+    fn fail(&mut self, path: &Path, e: anyhow::Error) {
+        self.stats.errors += 1;
+        eprintln!("⚠️  处理失败: {} — {}", path.display(), e);
+        self.failures.push((path.to_path_buf(), e.to_string()));
+    }
+
+    /// 第一遍：确定组的拍摄日期，按需读取元数据与地点
+    fn plan<'g>(&self, group: &'g MediaGroup) -> Result<Plan<'g>> {
+        let cli = self.cli;
+
+        // RAW 排在最前，优先采用其日期；全部没有日期才归入未分类
+        let paths: Vec<&Path> = group.items.iter().map(|item| item.path.as_path()).collect();
+        let mut extracted = self.dates.extract(&paths)?;
+
+        // 只在模板、事件标签、时钟校正或地点统计用到时读取元数据
+        let needs_metadata = self.layout.needs_metadata()
+            || self.renamer.is_some()
+            || self.geocoder.is_some()
+            || self.shifts.needs_metadata()
+            || cli.event_label.is_some();
+        let mut metadata = if extracted.is_some() && needs_metadata {
+            Metadata::for_group(group)
+        } else {
+            Metadata::default()
+        };
+
+        let shift = self.shifts.apply(&mut extracted, &metadata)?;
+        let filing_time = extracted.map(|(date, _)| date.filing_time(&cli.tz));
+
+        // XMP 中已有的地点信息优先，缺失的字段由 GPS 坐标补全
+        let place = match (&self.geocoder, metadata.gps) {
+            (Some(geocoder), Some((lat, lon))) => geocoder.lookup(lat, lon),
+            _ => None,
+        };
+        if let Some(Place { city, region, country }) = place.clone() {
+            metadata.city = metadata.city.or(Some(city));
+            metadata.region = metadata.region.or(Some(region));
+            metadata.country = metadata.country.or(Some(country));
+        }
+
+        Ok(Plan {
+            group,
+            extracted,
+            filing_time,
+            shift,
+            metadata,
+            place,
+        })
+    }
+
+    /// 本次运行的一个事件（最早时间 `start`，成员 `members`）对应的事件编号：
+    /// 与已建立的事件相隔不超过间隔时并入其中，否则建立新事件
+    fn known_event<'p, 'g: 'p>(
+        &mut self,
+        start: NaiveDateTime,
+        members: impl Iterator<Item = &'p Plan<'g>> + Clone,
+    ) -> usize {
+        let last = members.clone().filter_map(|plan| plan.filing_time).max().unwrap_or(start);
+        let gap = self.cli.event_gap;
+        let known = self
+            .events
+            .iter()
+            .position(|known| start - known.last <= gap && known.first - last <= gap);
+        if let Some(id) = known {
+            let known = &mut self.events[id];
+            known.first = known.first.min(start);
+            known.last = known.last.max(last);
+            return id;
+        }
+        let label = self
+            .cli
+            .event_label
+            .and_then(|label| event::most_common(members.filter_map(|plan| plan.label(label))));
+        self.events.push(KnownEvent {
+            start,
+            first: start,
+            last,
+            label,
+        });
+        self.events.len() - 1
+    }
+
+    /// 事件目录：多个事件渲染出同一目录时，后出现的事件追加 _2, _3, ... 后缀
+    fn event_dir(&mut self, event: usize, label: Option<&str>, dir: PathBuf) -> PathBuf {
+        let dir = match (label, dir.file_name()) {
+            (Some(label), Some(name)) => {
+                dir.with_file_name(format!("{} {}", name.to_string_lossy(), sanitize(label)))
+            }
+            _ => dir,
+        };
+        let mut candidate = dir.clone();
+        let mut n = 1;
+        loop {
+            match self.event_dirs.get(&candidate) {
+                Some(&owner) if owner != event => {
+                    n += 1;
+                    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+                    candidate = dir.with_file_name(format!("{}_{}", name, n));
+                }
+                _ => break,
+            }
+        }
+        self.event_dirs.insert(candidate.clone(), event);
+        candidate
+    }
+
+    /// 第二遍：确定组内各文件（连同附属文件）的目标路径
+    fn prepare(&mut self, plan: &Plan, folder: Option<Folder>) -> Result<Vec<Job>> {
+        let cli = self.cli;
+        let Plan {
+            group,
+            extracted,
+            filing_time,
+            shift,
+            ref metadata,
+            ref place,
+        } = *plan;
+
+        let primary = &group.items[0];
+        let target_subdir = match folder {
+            Some(Folder { time, event }) => {
+                let parent = primary.path.parent().unwrap_or(Path::new(""));
+                let ctx = DirContext {
+                    time,
+                    metadata,
+                    ext: primary.suffix(),
+                    source_dir: parent.strip_prefix(&self.source_dir).unwrap_or(parent),
+                };
+                let dir = self.output_dir.join(self.layout.render(&ctx)?);
+                match event {
+                    Some((index, label)) => self.event_dir(index, label.as_deref(), dir),
+                    None => dir,
+                }
+            }
+            None => self.output_dir.join("unsorted"),
+        };
+
+        // 与本次运行或目标目录中已有文件内容相同的成员跳过
+        let mut pending = Vec::new();
+        for item in &group.items {
+            let lookup = match self.dedup.as_mut() {
+                Some(index) => {
+                    index.scan_dir(&target_subdir);
+                    let lookup = index.lookup(&item.path)?;
+                    if let Some(original) = &lookup.duplicate_of {
+                        self.stats.duplicates += 1;
+                        if !cli.quiet {
+                            println!(
+                                "  重复: {} = {}",
+                                item.path.display(),
+                                original.display()
+                            );
+                        }
+                        // 附属文件可能与已有副本的不同（例如另行编辑过的 XMP），只提示不处理
+                        for sidecar in &item.sidecars {
+                            eprintln!(
+                                "⚠️  附属文件未处理: {}（所属文件与 {} 重复）",
+                                sidecar.path.display(),
+                                original.display()
+                            );
+                            self.stats.skipped_sidecars.push(sidecar.path.clone());
+                        }
+                        continue;
+                    }
+                    Some(lookup)
+                }
+                None => None,
+            };
+            pending.push((item, lookup));
+        }
+        if pending.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // 组内成员使用同一个目标主干（按模板重命名，并处理文件名冲突）
+        let base_stem = match (&mut self.renamer, filing_time) {
+            (Some(renamer), Some(time)) => {
+                let ctx = RenameContext {
+                    time,
+                    metadata,
+                    stem: &group.stem,
+                    ext: primary.suffix(),
+                };
+                let reserved = &self.reserved;
+                renamer.stem(&ctx, |stem| is_taken(&target_subdir, group, stem, reserved))?
+            }
+            // 无日期的文件保留原文件名
+            _ => group.stem.clone(),
+        };
+        let stem = resolve_conflict(&target_subdir, group, &base_stem, &self.reserved);
+        for name in group.target_names(&stem) {
+            self.reserved.insert(target_subdir.join(name));
+        }
+
+        let action = self.transfer.mode.describe();
+        let mut date_info = extracted
+            .map(|(date, source)| format!("{} | {}", date.describe(&cli.tz), source))
+            .unwrap_or_else(|| "无日期".to_string());
+        if let Some(shift) = shift {
+            date_info = format!("{} | ⏱ 校正 {}", date_info, shift);
+        }
+        if let Some(place) = place {
+            date_info = format!("{} | 📍 {}", date_info, place.describe());
+        }
+
+        let mut jobs = Vec::new();
+        for (item, lookup) in pending {
+            let target_name = item.target_name(&stem);
+            let target_path = target_subdir.join(&target_name);
+
+            // 目标已存在则跳过
+            if target_path.exists() {
+                self.stats.skipped += 1;
+                continue;
+            }
+
+            let sidecars: Vec<(PathBuf, PathBuf)> = item
+                .sidecars
+                .iter()
+                .map(|sc| (sc.path.clone(), target_subdir.join(sc.target_name(&target_name))))
+                .collect();
+
+            if !cli.quiet {
+                println!(
+                    "  {} {} → {} [{}]",
+                    if cli.dry_run {
+                        format!("[预览{}]", action)
+                    } else {
+                        format!("{}:", action)
+                    },
+                    item.path.display(),
+                    target_path.display(),
+                    date_info
+                );
+                for (source, target) in &sidecars {
+                    println!("      + {} → {}", source.display(), target.display());
+                }
+            }
+
+            // 目标文件要到第三遍才写入，查重时先读取源文件
+            if let (Some(index), Some(lookup)) = (self.dedup.as_mut(), &lookup) {
+                index.insert(target_path.clone(), item.path.clone(), lookup.size, lookup.digest);
+            }
+
+            jobs.push(Job {
+                source: item.path.clone(),
+                target: target_path,
+                digest: lookup.and_then(|l| l.digest),
+                sidecars,
+                filing_date: filing_time.map(|dt| dt.format("%Y-%m-%d").to_string()),
+                date_source: extracted.map(|(_, source)| source),
+                place: place.as_ref().map(Place::describe),
+                extension_fixed: item.extension_fixed,
+            });
+        }
+
+        Ok(jobs)
+    }
+}
+
+/// 第三遍：创建目标目录，传输文件及其附属文件，返回各文件的传输结果
+fn execute(
+    options: &TransferOptions,
+    journal: &Mutex<&mut Journal>,
+    job: &Job,
+) -> Result<Vec<Transferred>> {
+    if let Some(dir) = job.target.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("无法创建目录: {}", dir.display()))?;
+    }
+    let files = std::iter::once((&job.source, &job.target, job.digest))
+        .chain(job.sidecars.iter().map(|(source, target)| (source, target, None)));
+    files
+        .map(|(source, target, digest)| record_transfer(options, journal, source, target, digest))
+        .collect()
+}
+
+/// 传输单个文件，并写入操作日志
+fn record_transfer(
+    options: &TransferOptions,
+    journal: &Mutex<&mut Journal>,
+    source: &Path,
+    target: &Path,
+    digest: Option<Digest>,
+) -> Result<Transferred> {
+    let transferred = transfer::transfer(options, source, target)?;
+    // 链接与克隆不读取文件内容，没有现成的摘要时不记录，撤销时与源文件比对
+    let digest = match (digest.or(transferred.digest), transferred.operation) {
+        (Some(digest), _) => Some(digest),
+        (None, Operation::Copy | Operation::Move) => Some(hash::hash_file(target)?),
+        (None, _) => None,
+    };
+    let mut journal = journal.lock().unwrap_or_else(PoisonError::into_inner);
+    journal.record(transferred.operation, source, target, digest)?;
+    Ok(transferred)
+}
+
+/// 组内任一文件（或其附属文件）在给定主干下的目标是否已存在或已分配给其他组
+fn is_taken(dir: &Path, group: &MediaGroup, stem: &str, reserved: &HashSet<PathBuf>) -> bool {
+    group.target_names(stem).iter().any(|name| {
+        let path = dir.join(name);
+        path.exists() || reserved.contains(&path)
+    })
+}
+
+/// 解决文件名冲突：如果目标已被占用，为整组追加 _1, _2, ... 后缀
+fn resolve_conflict(
+    dir: &Path,
+    group: &MediaGroup,
+    stem: &str,
+    reserved: &HashSet<PathBuf>,
+) -> String {
+    if !is_taken(dir, group, stem, reserved) {
+        return stem.to_string();
+    }
+
+    for i in 1..10000 {
+        let new_stem = format!("{}_{}", stem, i);
+        if !is_taken(dir, group, &new_stem, reserved) {
+            return new_stem;
+        }
+    }
+
+    format!("{}_{}", stem, chrono::Utc::now().timestamp())
+}
@@ -0,0 +1,563 @@
+//! 精简的正则表达式引擎（回溯实现），用于文件名日期识别
+//!
+//! 支持：字面量与转义、`.`、字符类 `[a-z]` / `[^...]`、`\d \w \s`（及大写取反）、
+//! 锚点 `^ $`、分组 `(...)` / `(?:...)` / 命名分组 `(?P<name>...)` / `(?<name>...)`、
+//! 选择 `|`、量词 `* + ? {n} {n,} {n,m}`（后缀 `?` 为非贪婪），以及开头的 `(?i)` 忽略大小写。
+//!
+//! 回溯的总步数与递归深度都有上限：超出时按不匹配处理，避免病态模式（如 `(a+)+b`）
+//! 耗尽时间或栈空间。
+
+use std::cell::Cell;
+use std::fmt;
+
+/// 单次查找允许的匹配步数
+const MAX_STEPS: usize = 1_000_000;
+
+/// 匹配时的最大递归深度：足以匹配 255 字符的文件名，在 2 MiB 的线程栈内也不会溢出
+const MAX_DEPTH: usize = 1_000;
+
+/// 正则语法错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    pub pattern: String,
+    pub message: String,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "无效的正则表达式 `{}`: {}", self.pattern, self.message)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Start,
+    End,
+    Group { alts: Vec<Vec<Node>>, index: Option<usize> },
+    Repeat { node: Box<Node>, min: usize, max: Option<usize>, greedy: bool },
+}
+
+/// 编译后的正则表达式
+#[derive(Debug, Clone)]
+pub struct Regex {
+    nodes: Vec<Node>,
+    names: Vec<Option<String>>,
+    ignore_case: bool,
+}
+
+/// 匹配结果中的分组
+pub struct Captures<'r> {
+    regex: &'r Regex,
+    groups: Vec<Option<String>>,
+}
+
+impl Captures<'_> {
+    /// 按名称取分组内容
+    pub fn name(&self, name: &str) -> Option<&str> {
+        let index = self
+            .regex
+            .names
+            .iter()
+            .position(|n| n.as_deref() == Some(name))?;
+        self.groups.get(index)?.as_deref()
+    }
+}
+
+type Spans = Vec<Option<(usize, usize)>>;
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, PatternError> {
+        let (ignore_case, body) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: body.chars().collect(),
+            pos: 0,
+            names: Vec::new(),
+        };
+        let alts = parser.parse_alternation().map_err(|message| PatternError {
+            pattern: pattern.to_string(),
+            message,
+        })?;
+        if parser.pos < parser.chars.len() {
+            return Err(PatternError {
+                pattern: pattern.to_string(),
+                message: "括号不匹配".to_string(),
+            });
+        }
+        Ok(Regex {
+            nodes: vec![Node::Group { alts, index: None }],
+            names: parser.names,
+            ignore_case,
+        })
+    }
+
+    /// 是否包含指定名称的命名分组
+    pub fn has_group(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n.as_deref() == Some(name))
+    }
+
+    /// 在文本中查找第一个匹配，返回各分组内容
+    pub fn captures(&self, text: &str) -> Option<Captures<'_>> {
+        let chars: Vec<char> = text.chars().collect();
+        let matcher = Matcher {
+            regex: self,
+            text: &chars,
+            steps: Cell::new(MAX_STEPS),
+            depth: Cell::new(0),
+        };
+        for start in 0..=chars.len() {
+            let mut spans: Spans = vec![None; self.names.len()];
+            if matcher.steps.get() == 0 {
+                return None;
+            }
+            if matcher.run(&self.nodes, start, &mut spans, &mut |_, _| true) {
+                let groups = spans
+                    .into_iter()
+                    .map(|span| span.map(|(a, b)| chars[a..b].iter().collect()))
+                    .collect();
+                return Some(Captures {
+                    regex: self,
+                    groups,
+                });
+            }
+        }
+        None
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    names: Vec<Option<String>>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.peek().ok_or("表达式意外结束")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let n = s.chars().count();
+        if self.chars.len() >= self.pos + n
+            && self.chars[self.pos..self.pos + n].iter().copied().eq(s.chars())
+        {
+            self.pos += n;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternation(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alts = vec![self.parse_sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alts.push(self.parse_sequence()?);
+        }
+        Ok(alts)
+    }
+
+    fn parse_sequence(&mut self) -> Result<Vec<Node>, String> {
+        let mut seq = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            seq.push(self.parse_quantifier(atom)?);
+        }
+        Ok(seq)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        match self.next()? {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '[' => self.parse_class(),
+            '\\' => self.parse_escape(false),
+            '(' => {
+                let index = if self.eat("?:") {
+                    None
+                } else if self.eat("?P<") || self.eat("?<") {
+                    let mut name = String::new();
+                    loop {
+                        match self.next()? {
+                            '>' => break,
+                            c if c.is_alphanumeric() || c == '_' => name.push(c),
+                            c => return Err(format!("分组名中的非法字符 `{}`", c)),
+                        }
+                    }
+                    if name.is_empty() {
+                        return Err("分组名为空".to_string());
+                    }
+                    self.names.push(Some(name));
+                    Some(self.names.len() - 1)
+                } else if self.peek() == Some('?') {
+                    return Err("不支持的分组语法".to_string());
+                } else {
+                    self.names.push(None);
+                    Some(self.names.len() - 1)
+                };
+                let alts = self.parse_alternation()?;
+                if self.next()? != ')' {
+                    return Err("缺少 `)`".to_string());
+                }
+                Ok(Node::Group { alts, index })
+            }
+            c @ ('*' | '+' | '?') => Err(format!("量词 `{}` 前没有可重复的内容", c)),
+            '{' => {
+                // 不是合法重复次数的 `{` 按字面量处理
+                let save = self.pos;
+                let quantifier = self.parse_braces().is_some();
+                self.pos = save;
+                if quantifier {
+                    return Err("量词 `{` 前没有可重复的内容".to_string());
+                }
+                Ok(Node::Char('{'))
+            }
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    /// 解析转义；`in_class` 为真时返回的字符类会被展开到外层字符类中
+    fn parse_escape(&mut self, in_class: bool) -> Result<Node, String> {
+        let c = self.next()?;
+        let class = |ranges: Vec<(char, char)>, negated: bool| Node::Class { ranges, negated };
+        Ok(match c {
+            'd' => class(vec![('0', '9')], false),
+            'D' if !in_class => class(vec![('0', '9')], true),
+            'w' => class(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], false),
+            'W' if !in_class => class(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')], true),
+            's' => class(vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')], false),
+            'S' if !in_class => class(vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')], true),
+            't' => Node::Char('\t'),
+            'n' => Node::Char('\n'),
+            c if c.is_ascii_alphanumeric() => return Err(format!("不支持的转义 `\\{}`", c)),
+            c => Node::Char(c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.eat("^");
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.next().map_err(|_| "缺少 `]`".to_string())?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                match self.parse_escape(true)? {
+                    Node::Char(ch) => ch,
+                    Node::Class { ranges: r, .. } => {
+                        ranges.extend(r);
+                        continue;
+                    }
+                    _ => unreachable!(),
+                }
+            } else {
+                c
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']') {
+                self.pos += 1;
+                let hi = match self.next()? {
+                    '\\' => match self.parse_escape(true)? {
+                        Node::Char(ch) => ch,
+                        _ => return Err("字符范围的端点无效".to_string()),
+                    },
+                    ch => ch,
+                };
+                if hi < lo {
+                    return Err(format!("字符范围 `{}-{}` 顺序颠倒", lo, hi));
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let save = self.pos;
+                self.pos += 1;
+                match self.parse_braces() {
+                    Some(range) => {
+                        self.pos -= 1;
+                        range
+                    }
+                    None => {
+                        // 不是合法的重复次数，按字面量 `{` 处理
+                        self.pos = save;
+                        return Ok(atom);
+                    }
+                }
+            }
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End) {
+            return Err("锚点不能重复".to_string());
+        }
+        let greedy = !self.eat("?");
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// 解析 `n}`、`n,}`、`n,m}`；结束时指向 `}` 之后
+    fn parse_braces(&mut self) -> Option<(usize, Option<usize>)> {
+        let read_num = |p: &mut Parser| {
+            let start = p.pos;
+            while p.peek().is_some_and(|c| c.is_ascii_digit()) {
+                p.pos += 1;
+            }
+            let s: String = p.chars[start..p.pos].iter().collect();
+            s.parse::<usize>().ok()
+        };
+        let min = read_num(self)?;
+        let max = if self.eat(",") {
+            if self.peek() == Some('}') {
+                None
+            } else {
+                Some(read_num(self)?)
+            }
+        } else {
+            Some(min)
+        };
+        if !self.eat("}") || max.is_some_and(|m| m < min) {
+            return None;
+        }
+        Some((min, max))
+    }
+}
+
+struct Matcher<'a> {
+    regex: &'a Regex,
+    text: &'a [char],
+    /// 剩余的匹配步数，耗尽后所有分支都失败
+    steps: Cell<usize>,
+    depth: Cell<usize>,
+}
+
+type Cont<'c> = dyn FnMut(usize, &mut Spans) -> bool + 'c;
+
+impl Matcher<'_> {
+    fn char_eq(&self, a: char, b: char) -> bool {
+        a == b || (self.regex.ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    fn class_matches(&self, ranges: &[(char, char)], negated: bool, c: char) -> bool {
+        let hit = |ch: char| ranges.iter().any(|&(lo, hi)| lo <= ch && ch <= hi);
+        let mut matched = hit(c);
+        if !matched && self.regex.ignore_case {
+            matched = c.to_lowercase().any(hit) || c.to_uppercase().any(hit);
+        }
+        matched != negated
+    }
+
+    /// 从 `pos` 开始匹配节点序列，成功后调用续延 `k`；超出步数或深度上限时返回 false
+    fn run(&self, seq: &[Node], pos: usize, spans: &mut Spans, k: &mut Cont<'_>) -> bool {
+        let depth = self.depth.get();
+        if depth >= MAX_DEPTH {
+            self.steps.set(0);
+        }
+        if self.steps.get() == 0 {
+            return false;
+        }
+        self.steps.set(self.steps.get() - 1);
+        self.depth.set(depth + 1);
+        let matched = self.step(seq, pos, spans, k);
+        self.depth.set(depth);
+        matched
+    }
+
+    fn step(&self, seq: &[Node], pos: usize, spans: &mut Spans, k: &mut Cont<'_>) -> bool {
+        let Some((node, rest)) = seq.split_first() else {
+            return k(pos, spans);
+        };
+        let current = self.text.get(pos).copied();
+        match node {
+            Node::Char(c) => {
+                current.is_some_and(|ch| self.char_eq(*c, ch)) && self.run(rest, pos + 1, spans, k)
+            }
+            Node::Any => current.is_some() && self.run(rest, pos + 1, spans, k),
+            Node::Class { ranges, negated } => {
+                current.is_some_and(|ch| self.class_matches(ranges, *negated, ch))
+                    && self.run(rest, pos + 1, spans, k)
+            }
+            Node::Start => pos == 0 && self.run(rest, pos, spans, k),
+            Node::End => pos == self.text.len() && self.run(rest, pos, spans, k),
+            Node::Group { alts, index } => {
+                for alt in alts {
+                    let matched = self.run(alt, pos, spans, &mut |end, spans: &mut Spans| {
+                        let saved = index.map(|i| spans[i]);
+                        if let Some(i) = index {
+                            spans[*i] = Some((pos, end));
+                        }
+                        if self.run(rest, end, spans, k) {
+                            return true;
+                        }
+                        if let (Some(i), Some(old)) = (index, saved) {
+                            spans[*i] = old;
+                        }
+                        false
+                    });
+                    if matched {
+                        return true;
+                    }
+                }
+                false
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => self.repeat(node, *min, *max, *greedy, 0, pos, rest, spans, k),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &self,
+        node: &Node,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+        count: usize,
+        pos: usize,
+        rest: &[Node],
+        spans: &mut Spans,
+        k: &mut Cont<'_>,
+    ) -> bool {
+        let can_more = max.is_none_or(|m| count < m);
+        let try_more = |spans: &mut Spans, k: &mut Cont<'_>| {
+            can_more
+                && self.run(
+                    std::slice::from_ref(node),
+                    pos,
+                    spans,
+                    &mut |next, spans: &mut Spans| {
+                        // 零宽匹配不再继续重复，避免死循环
+                        if next == pos && count >= min {
+                            return false;
+                        }
+                        self.repeat(node, min, max, greedy, count + 1, next, rest, spans, k)
+                    },
+                )
+        };
+        if greedy {
+            if try_more(spans, k) {
+                return true;
+            }
+            count >= min && self.run(rest, pos, spans, k)
+        } else {
+            if count >= min && self.run(rest, pos, spans, k) {
+                return true;
+            }
+            try_more(spans, k)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str, group: &str) -> Option<String> {
+        let regex = Regex::new(pattern).unwrap();
+        let caps = regex.captures(text)?;
+        Some(caps.name(group).unwrap_or_default().to_string())
+    }
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().captures(text).is_some()
+    }
+
+    #[test]
+    fn classes_and_escapes() {
+        assert!(is_match(r"^[a-c]+\d$", "abca7"));
+        assert!(!is_match(r"^[a-c]+\d$", "abda7"));
+        assert!(is_match(r"^[^0-9]\D\w\s\S$", "x-_ y"));
+        assert!(is_match(r"^[\d_-]+$", "12_3-4"));
+        assert!(is_match(r"^[]a]+$", "]a]"));
+        assert!(is_match(r"^a\.b\\$", r"a.b\"));
+        assert!(!is_match(r"^a\.b$", "axb"));
+        assert!(is_match(r"(?i)^[a-c]X$", "Bx"));
+        assert!(!is_match(r"^[a-c]X$", "Bx"));
+    }
+
+    #[test]
+    fn alternation_anchors_and_quantifiers() {
+        assert!(is_match("^(?:cat|dog)s?$", "dogs"));
+        assert!(!is_match("^(?:cat|dog)s?$", "cow"));
+        assert!(is_match("b$", "ab"));
+        assert!(!is_match("^b", "ab"));
+        assert!(is_match(r"^\d{2,3}$", "123"));
+        assert!(!is_match(r"^\d{2,3}$", "1234"));
+        assert!(is_match(r"^\d{2,}$", "1234"));
+        assert!(is_match("^a{x}$", "a{x}"));
+        assert_eq!(find("(?P<n>a+?)", "aaa", "n").as_deref(), Some("a"));
+        assert_eq!(find("(?P<n>a+)", "aaa", "n").as_deref(), Some("aaa"));
+        assert_eq!(find("(?P<n>a*)*$", "aaa", "n").as_deref(), Some("aaa"));
+    }
+
+    #[test]
+    fn named_groups() {
+        let regex = Regex::new(r"(?P<year>\d{4})-(?<month>\d{2})(?:-(?P<day>\d{2}))?").unwrap();
+        assert!(regex.has_group("month") && !regex.has_group("hour"));
+        let caps = regex.captures("x2023-06y").unwrap();
+        assert_eq!(caps.name("year"), Some("2023"));
+        assert_eq!(caps.name("month"), Some("06"));
+        assert_eq!(caps.name("day"), None);
+        // 回溯时撤销失败分支中的分组
+        assert_eq!(find("(?:(?P<a>x)y|xz)", "xz", "a").as_deref(), Some(""));
+        assert_eq!(find("(?:(?P<a>x)y|xz)", "xy", "a").as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        for pattern in ["(a", "a)", "[a", "[z-a]", "*a", "^*", r"\q", "(?=a)", "(?P<>a)", "{2}a"] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn pathological_patterns_are_bounded() {
+        let text = "a".repeat(40);
+        let start = std::time::Instant::now();
+        assert!(!is_match("(a+)+b", &text));
+        assert!(!is_match("(?:a|aa)*c", &text));
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+        // 递归深度超过上限时不匹配，而不是栈溢出
+        let long = "a".repeat(MAX_DEPTH * 4);
+        assert!(!is_match("^a*$", &long));
+        assert!(!is_match("^(?:){100000}$", ""));
+        assert!(is_match("^a*$", &"a".repeat(255)));
+    }
+}
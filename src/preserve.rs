@@ -0,0 +1,285 @@
+//! 复制后保留源文件的属性（`--preserve times,mode,xattrs`）
+
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// 可保留的文件属性
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Attribute {
+    /// 修改时间与访问时间
+    Times,
+    /// 权限位
+    Mode,
+    /// 扩展属性（Linux 的 user.* 等）
+    Xattrs,
+}
+
+/// 要保留的属性集合
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Preserve {
+    pub times: bool,
+    pub mode: bool,
+    pub xattrs: bool,
+}
+
+impl Preserve {
+    pub fn new(attributes: &[Attribute]) -> Self {
+        Preserve {
+            times: attributes.contains(&Attribute::Times),
+            mode: attributes.contains(&Attribute::Mode),
+            xattrs: attributes.contains(&Attribute::Xattrs),
+        }
+    }
+
+    /// 全部属性
+    pub fn all() -> Self {
+        Preserve {
+            times: true,
+            mode: true,
+            xattrs: true,
+        }
+    }
+
+    /// 以 `times,mode` 形式显示
+    pub fn describe(&self) -> String {
+        let names = [(self.times, "times"), (self.mode, "mode"), (self.xattrs, "xattrs")];
+        let names: Vec<&str> = names.iter().filter(|(on, _)| *on).map(|(_, n)| *n).collect();
+        names.join(",")
+    }
+}
+
+/// Linux 上表示文件系统不支持扩展属性的错误码（EOPNOTSUPP，即 ENOTSUP）
+#[cfg(target_os = "linux")]
+const EOPNOTSUPP: i32 = 95;
+
+/// 保留属性的结果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Preserved {
+    /// 目标文件系统（如 exFAT、vfat、部分 NFS）不支持扩展属性，已跳过
+    pub xattrs_unsupported: bool,
+}
+
+/// 把 `source` 的属性应用到刚写出的 `target`
+pub fn apply(source: &Path, target: &Path, preserve: Preserve) -> io::Result<Preserved> {
+    let mut preserved = Preserved::default();
+    if preserve == Preserve::default() {
+        return Ok(preserved);
+    }
+    let meta = fs::metadata(source)?;
+    if preserve.xattrs {
+        // 复制出的文件继承了源文件的权限；非 root 用户不能给只读文件写入 user.* 扩展属性，
+        // 写入期间临时加上属主写权限
+        let original = fs::metadata(target)?.permissions();
+        let writable = make_writable(target, &original)?;
+        preserved.xattrs_unsupported = !copy_xattrs(source, target)?;
+        if writable && !preserve.mode {
+            fs::set_permissions(target, original)?;
+        }
+    }
+    // 时间通过只读打开的文件设置，先于权限设置也不会被之后的操作改动；
+    // 这样只读的源文件（0444）复制出的文件也能设置时间
+    if preserve.times {
+        let mut times = fs::FileTimes::new().set_modified(meta.modified()?);
+        if let Ok(accessed) = meta.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        fs::File::open(target)?.set_times(times)?;
+    }
+    // 最后设置权限，之前的写入不受只读权限影响
+    if preserve.mode {
+        fs::set_permissions(target, meta.permissions())?;
+    }
+    Ok(preserved)
+}
+
+/// 属主没有写权限时加上，返回是否改动过
+#[cfg(unix)]
+fn make_writable(path: &Path, permissions: &fs::Permissions) -> io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = permissions.mode();
+    if mode & 0o200 != 0 {
+        return Ok(false);
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(mode | 0o200))?;
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn make_writable(_path: &Path, _permissions: &fs::Permissions) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// 复制全部扩展属性；无权限写入的系统命名空间（security.*、trusted.* 等）跳过。
+/// 目标文件系统不支持扩展属性时返回 `Ok(false)`
+#[cfg(target_os = "linux")]
+fn copy_xattrs(source: &Path, target: &Path) -> io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::raw::c_void;
+    use sys::{c_path, lgetxattr, llistxattr, lsetxattr};
+
+    let (src, dst) = (c_path(source)?, c_path(target)?);
+
+    // 先以长度 0 查询所需缓冲区大小，再读取
+    // SAFETY: 路径是以 NUL 结尾的 C 字符串，缓冲区长度与传入的 size 一致
+    let read = |query: &dyn Fn(*mut c_void, usize) -> isize| -> io::Result<Vec<u8>> {
+        let size = query(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let len = query(buf.as_mut_ptr().cast(), buf.len());
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf.truncate(len as usize);
+        Ok(buf)
+    };
+
+    let names = match read(&|buf, size| unsafe { llistxattr(src.as_ptr(), buf.cast(), size) }) {
+        Ok(names) => names,
+        // 源文件系统不支持扩展属性：没有可复制的内容
+        Err(e) if e.raw_os_error() == Some(EOPNOTSUPP) => return Ok(true),
+        Err(e) => return Err(e),
+    };
+    for name in names.split(|&b| b == 0).filter(|n| !n.is_empty()) {
+        let c_name = CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let value = read(&|buf, size| unsafe {
+            lgetxattr(src.as_ptr(), c_name.as_ptr(), buf, size)
+        })?;
+        let ret = unsafe {
+            lsetxattr(dst.as_ptr(), c_name.as_ptr(), value.as_ptr().cast(), value.len(), 0)
+        };
+        if ret != 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(EOPNOTSUPP) {
+                return Ok(false);
+            }
+            if name.starts_with(b"user.") {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("无法写入扩展属性 {}: {}", String::from_utf8_lossy(name), err),
+                ));
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// glibc 的扩展属性接口
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    extern "C" {
+        pub fn llistxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize;
+        pub fn lgetxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+        ) -> isize;
+        pub fn lsetxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+            flags: c_int,
+        ) -> c_int;
+    }
+
+    /// 路径转换为以 NUL 结尾的 C 字符串
+    pub fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+}
+
+/// 其他系统暂不支持复制扩展属性
+#[cfg(not(target_os = "linux"))]
+fn copy_xattrs(_source: &Path, _target: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 设置扩展属性；文件系统不支持时返回 false
+    #[cfg(target_os = "linux")]
+    fn set_xattr(path: &Path, name: &str, value: &[u8]) -> bool {
+        let (path, name) = (sys::c_path(path).unwrap(), std::ffi::CString::new(name).unwrap());
+        // SAFETY: 两个参数都是以 NUL 结尾的 C 字符串，值的长度与切片一致
+        let ret = unsafe {
+            sys::lsetxattr(path.as_ptr(), name.as_ptr(), value.as_ptr().cast(), value.len(), 0)
+        };
+        if ret != 0 {
+            let err = io::Error::last_os_error();
+            assert_eq!(err.raw_os_error(), Some(EOPNOTSUPP), "{}", err);
+        }
+        ret == 0
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_xattr(path: &Path, name: &str) -> Option<Vec<u8>> {
+        let (path, name) = (sys::c_path(path).unwrap(), std::ffi::CString::new(name).unwrap());
+        let mut buf = vec![0u8; 256];
+        // SAFETY: 缓冲区长度与传入的 size 一致
+        let len = unsafe {
+            sys::lgetxattr(path.as_ptr(), name.as_ptr(), buf.as_mut_ptr().cast(), buf.len())
+        };
+        (len >= 0).then(|| buf[..len as usize].to_vec())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn round_trips_attributes_of_read_only_source() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, SystemTime};
+
+        let dir = std::env::temp_dir().join(format!("porg-preserve-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, target) = (dir.join("source.jpg"), dir.join("target.jpg"));
+        fs::write(&source, b"photo").unwrap();
+
+        #[cfg(target_os = "linux")]
+        let xattrs = set_xattr(&source, "user.porg.test", b"value");
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_686_824_100);
+        let times = fs::FileTimes::new().set_modified(modified).set_accessed(modified);
+        fs::File::options().write(true).open(&source).unwrap().set_times(times).unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o444)).unwrap();
+        // 与传输时一样由 fs::copy 写出，目标继承只读权限
+        fs::copy(&source, &target).unwrap();
+
+        let only_xattrs = Preserve::new(&[Attribute::Xattrs]);
+        apply(&source, &target, only_xattrs).unwrap();
+        assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o444);
+        let preserved = apply(&source, &target, Preserve::all()).unwrap();
+        assert!(!preserved.xattrs_unsupported);
+        let meta = fs::metadata(&target).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o444);
+        assert_eq!(meta.modified().unwrap(), modified);
+        #[cfg(target_os = "linux")]
+        if xattrs {
+            assert_eq!(get_xattr(&target, "user.porg.test").as_deref(), Some(&b"value"[..]));
+        }
+
+        // 只读的目标再次设置时间也不需要写权限
+        assert!(apply(&source, &target, Preserve::new(&[Attribute::Times])).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn describes_attributes() {
+        assert_eq!(Preserve::all().describe(), "times,mode,xattrs");
+        let preserve = Preserve::new(&[Attribute::Xattrs, Attribute::Times]);
+        assert_eq!(preserve.describe(), "times,xattrs");
+        assert_eq!(Preserve::default().describe(), "");
+    }
+}
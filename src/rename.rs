@@ -0,0 +1,188 @@
+//! 文件重命名模板（`--rename`）
+//!
+//! 模板生成组的新文件名主干，各成员保留自己的扩展名；末尾的 `{ext}` 可省略。
+
+use crate::metadata::Metadata;
+use crate::template::{sanitize, validate_strftime, Segment, Template};
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+use std::collections::HashMap;
+
+/// 重命名模板可用的占位符
+pub const RENAME_PLACEHOLDERS: &[&str] = &["date", "subsec", "make", "model", "stem", "ext", "seq"];
+
+/// `{date}` 的默认格式
+const DEFAULT_DATE_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// 为一个文件查找可用 `{seq}` 序号的最多尝试次数
+const MAX_SEQ_ATTEMPTS: u64 = 10_000;
+
+/// 渲染文件名所需的信息
+pub struct RenameContext<'a> {
+    /// 归档时间
+    pub time: NaiveDateTime,
+    pub metadata: &'a Metadata,
+    /// 原文件名主干
+    pub stem: &'a str,
+    /// 主文件的扩展名（含 `.`）
+    pub ext: &'a str,
+}
+
+/// 按模板生成文件名，并维护按天计数的序号
+pub struct Renamer {
+    template: Template,
+    counters: HashMap<NaiveDate, u64>,
+}
+
+impl Renamer {
+    pub fn new(source: &str) -> Result<Self> {
+        let template = Template::parse(source)?;
+        template.check_names(RENAME_PLACEHOLDERS)?;
+        for segment in template.segments() {
+            if let Segment::Literal(text) = segment {
+                if text.contains('/') || text.contains('\\') {
+                    anyhow::bail!("重命名模板 `{}` 不能包含路径分隔符", template);
+                }
+            }
+            if let Segment::Placeholder { name, arg: Some(arg) } = segment {
+                match name.as_str() {
+                    "date" => validate_strftime(arg)?,
+                    "seq" | "subsec" if arg.parse::<usize>().is_err() => {
+                        anyhow::bail!("{{{}:{}}} 的参数应为位数，如 {{{}:03}}", name, arg, name)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Renamer {
+            template,
+            counters: HashMap::new(),
+        })
+    }
+
+    /// 生成组的新文件名主干
+    ///
+    /// 使用 `{seq}` 时跳过已被占用的序号（`is_taken` 判断），同一天的序号在本次运行中递增；
+    /// 否则冲突交由调用方追加 `_N` 后缀。
+    pub fn stem(&mut self, ctx: &RenameContext, is_taken: impl Fn(&str) -> bool) -> Result<String> {
+        if !self.template.uses("seq") {
+            return self.render(ctx, 0);
+        }
+
+        let day = ctx.time.date();
+        let first = self.counters.get(&day).copied().unwrap_or(1);
+        for seq in first..first.saturating_add(MAX_SEQ_ATTEMPTS) {
+            let stem = self.render(ctx, seq)?;
+            if !is_taken(&stem) {
+                self.counters.insert(day, seq + 1);
+                return Ok(stem);
+            }
+        }
+        anyhow::bail!(
+            "重命名模板 `{}` 在 {} 找不到未占用的序号（已尝试 {} 至 {}）",
+            self.template,
+            day,
+            first,
+            first.saturating_add(MAX_SEQ_ATTEMPTS - 1)
+        )
+    }
+
+    fn render(&self, ctx: &RenameContext, seq: u64) -> Result<String> {
+        let name = self.template.render(|name, arg| {
+            Ok(match name {
+                "date" => ctx
+                    .time
+                    .format(arg.unwrap_or(DEFAULT_DATE_FORMAT))
+                    .to_string(),
+                "subsec" => {
+                    let width = arg.and_then(|a| a.parse().ok()).unwrap_or(3).clamp(1, 9);
+                    let nanos = format!("{:09}", ctx.time.nanosecond() % 1_000_000_000);
+                    nanos[..width].to_string()
+                }
+                "make" => sanitize(ctx.metadata.make.as_deref().unwrap_or("")),
+                "model" => sanitize(ctx.metadata.model.as_deref().unwrap_or("")),
+                "stem" => ctx.stem.to_string(),
+                "ext" => ctx.ext.to_string(),
+                "seq" => {
+                    let width = arg.and_then(|a| a.parse().ok()).unwrap_or(0);
+                    format!("{:0width$}", seq, width = width)
+                }
+                _ => unreachable!("占位符已在解析时校验"),
+            })
+        })?;
+
+        if name.contains('/') || name.contains('\\') {
+            anyhow::bail!("重命名模板 `{}` 生成的文件名包含路径分隔符: {}", self.template, name);
+        }
+        // 扩展名由各成员自己保留
+        let stem = match name.strip_suffix(ctx.ext) {
+            Some(stem) if !ctx.ext.is_empty() => stem,
+            _ => name.as_str(),
+        };
+        if stem.is_empty() {
+            anyhow::bail!("重命名模板 `{}` 生成了空文件名", self.template);
+        }
+        Ok(stem.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn context<'a>(metadata: &'a Metadata, time: &str) -> RenameContext<'a> {
+        RenameContext {
+            time: NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.f").unwrap(),
+            metadata,
+            stem: "IMG_0001",
+            ext: ".JPG",
+        }
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let metadata = Metadata {
+            make: Some("Apple".to_string()),
+            model: Some("iPhone 12/Pro".to_string()),
+            ..Default::default()
+        };
+        let ctx = context(&metadata, "2023-06-15 10:15:00.123456");
+        let mut renamer = Renamer::new("{date}_{subsec}_{model}_{stem}{ext}").unwrap();
+        assert_eq!(
+            renamer.stem(&ctx, |_| false).unwrap(),
+            "20230615_101500_123_iPhone 12_Pro_IMG_0001"
+        );
+        let mut renamer = Renamer::new("{{{make}}}-{subsec:6}").unwrap();
+        assert_eq!(renamer.stem(&ctx, |_| false).unwrap(), "{Apple}-123456");
+        // 生成的文件名中含路径分隔符时报错
+        let mut renamer = Renamer::new("{date:%Y\\%m}").unwrap();
+        assert!(renamer.stem(&ctx, |_| false).is_err());
+    }
+
+    #[test]
+    fn numbers_collisions_per_day() {
+        let metadata = Metadata::default();
+        let mut renamer = Renamer::new("{date:%Y%m%d}_{seq:03}").unwrap();
+        let taken: HashSet<&str> = ["20230615_001", "20230615_002"].into();
+        let day1 = context(&metadata, "2023-06-15 10:15:00");
+        let day2 = context(&metadata, "2023-06-16 08:00:00");
+        assert_eq!(renamer.stem(&day1, |s| taken.contains(s)).unwrap(), "20230615_003");
+        assert_eq!(renamer.stem(&day1, |s| taken.contains(s)).unwrap(), "20230615_004");
+        assert_eq!(renamer.stem(&day2, |s| taken.contains(s)).unwrap(), "20230616_001");
+
+        // 全部被占用时报错而不是无限尝试
+        let error = renamer.stem(&day1, |_| true).unwrap_err();
+        assert!(error.to_string().contains("未占用的序号"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for template in ["{date:%Q}", "{seq:x}", "{place}", "a/{date}", "{date", "{}"] {
+            assert!(Renamer::new(template).is_err(), "{}", template);
+        }
+        let metadata = Metadata::default();
+        let mut renamer = Renamer::new("{ext}").unwrap();
+        assert!(renamer.stem(&context(&metadata, "2023-06-15 10:15:00"), |_| false).is_err());
+    }
+}
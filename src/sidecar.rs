@@ -0,0 +1,128 @@
+//! 附属文件（XMP 等 sidecar）：按文件名与主文件关联，随主文件一起复制/移动
+
+use crate::media;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// 支持的附属文件扩展名（Lightroom/darktable、RawTherapee、DxO、Apple 照片编辑）
+pub const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "pp3", "dop", "aae"];
+
+/// 与主文件关联的附属文件
+#[derive(Debug, Clone)]
+pub struct Sidecar {
+    pub path: PathBuf,
+    /// 文件名中主文件名之后的部分，如 `.xmp`、`.NEF.xmp`
+    suffix: String,
+    /// 以主文件完整文件名为前缀（`DSC_1234.NEF.xmp`），否则以主文件名主干为前缀（`DSC_1234.xmp`）
+    full_name: bool,
+}
+
+impl Sidecar {
+    /// 根据主文件的目标文件名得到附属文件的目标文件名
+    pub fn target_name(&self, primary_name: &str) -> String {
+        if self.full_name {
+            format!("{}{}", primary_name, self.suffix)
+        } else {
+            format!("{}{}", file_stem(primary_name), self.suffix)
+        }
+    }
+}
+
+/// 判断文件是否是支持的附属文件
+pub fn is_sidecar(path: &Path) -> bool {
+    media::has_extension(path, SIDECAR_EXTENSIONS)
+}
+
+/// 将附属文件分配给同目录下的主文件，返回与 `primaries` 一一对应的列表
+///
+/// `DSC_1234.NEF.xmp` 优先匹配完整文件名 `DSC_1234.NEF`；`DSC_1234.xmp` 匹配文件名主干，
+/// 同名主干有多个主文件时优先分配给 RAW 文件。找不到主文件的附属文件保持原样。
+pub fn match_sidecars(primaries: &[PathBuf], sidecars: Vec<PathBuf>) -> Vec<Vec<Sidecar>> {
+    let mut by_name: HashMap<(&Path, &str), usize> = HashMap::new();
+    let mut by_stem: HashMap<(&Path, &str), usize> = HashMap::new();
+
+    for (i, path) in primaries.iter().enumerate() {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str()))
+        else {
+            continue;
+        };
+        by_name.insert((parent, name), i);
+        by_stem
+            .entry((parent, file_stem(name)))
+            .and_modify(|prev| {
+                if !media::is_raw(&primaries[*prev]) && media::is_raw(path) {
+                    *prev = i;
+                }
+            })
+            .or_insert(i);
+    }
+
+    let mut result = vec![Vec::new(); primaries.len()];
+    for path in sidecars {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str()))
+        else {
+            continue;
+        };
+        let key = file_stem(name);
+        let suffix = name[key.len()..].to_string();
+
+        let matched = match by_name.get(&(parent, key)) {
+            Some(&i) => Some((i, true)),
+            None => by_stem.get(&(parent, key)).map(|&i| (i, false)),
+        };
+        if let Some((i, full_name)) = matched {
+            result[i].push(Sidecar {
+                path: path.clone(),
+                suffix,
+                full_name,
+            });
+        }
+    }
+    result
+}
+
+/// 文件名去掉最后一个扩展名
+fn file_stem(name: &str) -> &str {
+    Path::new(name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(sidecars: &[Sidecar]) -> Vec<&Path> {
+        sidecars.iter().map(|sc| sc.path.as_path()).collect()
+    }
+
+    #[test]
+    fn matches_full_name_before_stem() {
+        let primaries = ["d/DSC_1.JPG", "d/DSC_1.NEF", "d/IMG_2.jpg", "e/IMG_2.heic"]
+            .map(PathBuf::from);
+        let sidecars = ["d/DSC_1.JPG.xmp", "d/DSC_1.xmp", "d/IMG_2.AAE", "e/IMG_2.pp3", "d/x.xmp"]
+            .map(PathBuf::from)
+            .to_vec();
+        let matched = match_sidecars(&primaries, sidecars);
+
+        assert_eq!(names(&matched[0]), [Path::new("d/DSC_1.JPG.xmp")]);
+        // 主干相同的 JPEG 与 RAW 中优先分配给 RAW
+        assert_eq!(names(&matched[1]), [Path::new("d/DSC_1.xmp")]);
+        assert_eq!(names(&matched[2]), [Path::new("d/IMG_2.AAE")]);
+        // 只与同目录的主文件关联
+        assert_eq!(names(&matched[3]), [Path::new("e/IMG_2.pp3")]);
+
+        assert_eq!(matched[0][0].target_name("20240101.JPG"), "20240101.JPG.xmp");
+        assert_eq!(matched[1][0].target_name("20240101.NEF"), "20240101.xmp");
+        assert_eq!(matched[2][0].target_name("b.jpg"), "b.AAE");
+    }
+
+    #[test]
+    fn recognizes_sidecar_extensions() {
+        assert!(is_sidecar(Path::new("a.XMP")));
+        assert!(is_sidecar(Path::new("a.NEF.dop")));
+        assert!(!is_sidecar(Path::new("a.jpg")));
+        assert!(!is_sidecar(Path::new("xmp")));
+    }
+}
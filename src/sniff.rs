@@ -0,0 +1,194 @@
+//! 按文件头（魔数）识别文件格式，用于扩展名错误或缺失的文件
+//!
+//! 识别 JPEG、PNG、TIFF 及基于 TIFF 的 RAW、HEIC/AVIF、WebP 与 QuickTime/MP4 视频；
+//! 无法识别时调用方退回按扩展名判断。
+
+use crate::media;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// 读取的文件头长度，足以包含 ISO-BMFF `ftyp` 盒子中常见的兼容品牌
+const HEADER_LEN: u64 = 64;
+
+/// 按内容识别出的文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    /// TIFF 及 NEF、ARW、DNG 等基于 TIFF 的 RAW（文件头无法区分）
+    Tiff,
+    Cr2,
+    Orf,
+    Rw2,
+    Heic,
+    Avif,
+    Webp,
+    Mov,
+    Mp4,
+    ThreeGp,
+}
+
+impl Format {
+    /// 与该格式相符的扩展名（小写），第一个用于修正扩展名
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Jpeg => &["jpg", "jpeg"],
+            Format::Png => &["png"],
+            Format::Tiff => &["tif", "tiff", "nef", "arw", "dng", "pef", "srw"],
+            Format::Cr2 => &["cr2"],
+            Format::Orf => &["orf"],
+            Format::Rw2 => &["rw2"],
+            Format::Heic => &["heic", "heif"],
+            Format::Avif => &["avif"],
+            Format::Webp => &["webp"],
+            Format::Mov => &["mov"],
+            Format::Mp4 => &["mp4", "m4v"],
+            Format::ThreeGp => &["3gp"],
+        }
+    }
+
+    pub fn is_video(self) -> bool {
+        matches!(self, Format::Mov | Format::Mp4 | Format::ThreeGp)
+    }
+}
+
+/// 读取文件头识别格式；无法读取或无法识别时返回 `None`
+pub fn detect(path: &Path) -> Option<Format> {
+    let file = fs::File::open(path).ok()?;
+    let mut header = Vec::new();
+    file.take(HEADER_LEN).read_to_end(&mut header).ok()?;
+    from_header(&header)
+}
+
+/// 根据文件开头的字节识别格式
+pub fn from_header(header: &[u8]) -> Option<Format> {
+    let at = |range: std::ops::Range<usize>| header.get(range);
+
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(Format::Jpeg);
+    }
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(Format::Png);
+    }
+    if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        // CR2 在 TIFF 文件头之后带有 `CR` 标记
+        return Some(if at(8..10) == Some(b"CR") { Format::Cr2 } else { Format::Tiff });
+    }
+    if header.starts_with(b"IIRO") || header.starts_with(b"IIRS") || header.starts_with(b"MMOR") {
+        return Some(Format::Orf);
+    }
+    if header.starts_with(b"IIU\0") {
+        return Some(Format::Rw2);
+    }
+    if header.starts_with(b"RIFF") && at(8..12) == Some(b"WEBP") {
+        return Some(Format::Webp);
+    }
+
+    match at(4..8)? {
+        b"ftyp" => {
+            let size = u32::from_be_bytes(header[0..4].try_into().ok()?) as usize;
+            let end = size.min(header.len());
+            let major = at(8..12)?;
+            let compatible = header.get(16..end).unwrap_or_default().chunks_exact(4);
+            from_brands(std::iter::once(major).chain(compatible))
+        }
+        // 不带 ftyp 的旧 QuickTime 文件直接以这些盒子开头
+        b"moov" | b"mdat" | b"wide" | b"free" | b"skip" | b"pnot" => Some(Format::Mov),
+        _ => None,
+    }
+}
+
+/// 按 ISO-BMFF 品牌（主品牌在前）判断格式
+fn from_brands<'a>(mut brands: impl Iterator<Item = &'a [u8]> + Clone) -> Option<Format> {
+    // CR3 同样是 ISO-BMFF 容器，但不是支持的格式
+    if brands.clone().next() == Some(b"crx ") {
+        return None;
+    }
+    let specific = brands.clone().find_map(|brand| match brand {
+        b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"hevm" | b"hevs" => {
+            Some(Format::Heic)
+        }
+        b"avif" | b"avis" => Some(Format::Avif),
+        b"qt  " => Some(Format::Mov),
+        [b'3', b'g', ..] => Some(Format::ThreeGp),
+        b"isom" | b"iso2" | b"iso4" | b"iso5" | b"iso6" | b"mp41" | b"mp42" | b"avc1" | b"dash"
+        | b"mmp4" | b"MSNV" | b"M4V " | b"M4VH" | b"M4VP" | b"f4v " => Some(Format::Mp4),
+        _ => None,
+    });
+    // 只声明通用 HEIF 品牌时按 HEIC 处理
+    specific.or_else(|| {
+        brands
+            .any(|brand| brand == b"mif1" || brand == b"msf1")
+            .then_some(Format::Heic)
+    })
+}
+
+/// 内容与扩展名不符（或没有扩展名）时应使用的扩展名；与原扩展名大小写风格一致
+///
+/// 许多 RAW（NRW、3FR、ERF、MOS、KDC、SR2 等）同样以 TIFF 文件头开头，因此识别为 TIFF 的文件
+/// 只在扩展名是其他已知图片/视频格式（如 `.jpg`）时才修正。
+pub fn corrected_extension(path: &Path) -> Option<String> {
+    let format = detect(path)?;
+    let ext = path.extension().and_then(|e| e.to_str());
+    if ext.is_some_and(|ext| format.extensions().contains(&ext.to_lowercase().as_str())) {
+        return None;
+    }
+    if format == Format::Tiff && !media::is_supported(path) {
+        return None;
+    }
+    let corrected = format.extensions()[0];
+    let uppercase = ext.is_some_and(|ext| {
+        ext.chars().any(|c| c.is_ascii_uppercase()) && !ext.chars().any(|c| c.is_ascii_lowercase())
+    });
+    Some(if uppercase { corrected.to_uppercase() } else { corrected.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_signatures() {
+        assert_eq!(from_header(b"\xFF\xD8\xFF\xE1\0\0Exif"), Some(Format::Jpeg));
+        assert_eq!(from_header(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(Format::Png));
+        assert_eq!(from_header(b"II*\0\x10\0\0\0CR\x02\0"), Some(Format::Cr2));
+        assert_eq!(from_header(b"MM\0*\0\0\0\x08"), Some(Format::Tiff));
+        assert_eq!(from_header(b"RIFF\x24\0\0\0WEBPVP8 "), Some(Format::Webp));
+        assert_eq!(from_header(b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"), Some(Format::Heic));
+        assert_eq!(from_header(b"\0\0\0\x18ftypmif1\0\0\0\0mif1avif"), Some(Format::Avif));
+        assert_eq!(from_header(b"\0\0\0\x14ftypqt  \0\0\x02\0qt  "), Some(Format::Mov));
+        assert_eq!(from_header(b"\0\0\0\x18ftypisom\0\0\x02\0isommp41"), Some(Format::Mp4));
+        assert_eq!(from_header(b"\0\0\0\x18ftypcrx \0\0\0\x01crx isom"), None);
+        assert_eq!(from_header(b"\0\0\0\x08wide\0\0\0\0mdat"), Some(Format::Mov));
+        assert_eq!(from_header(b"%PDF-1.7"), None);
+        assert_eq!(from_header(b""), None);
+    }
+
+    #[test]
+    fn corrects_mismatched_extensions() {
+        let dir = std::env::temp_dir().join(format!("porg-sniff-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+        for (name, content) in [
+            ("a.JPG", &heic[..]),
+            ("b.jpeg", b"\xFF\xD8\xFF\xE0"),
+            ("c", b"\xFF\xD8\xFF\xE0"),
+            ("d.txt", b"hello"),
+            ("e.NRW", b"II*\0\x08\0\0\0"),
+            ("f", b"MM\0*\0\0\0\x08"),
+            ("g.jpg", b"MM\0*\0\0\0\x08"),
+        ] {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        assert_eq!(corrected_extension(&dir.join("a.JPG")).as_deref(), Some("HEIC"));
+        assert_eq!(corrected_extension(&dir.join("b.jpeg")), None);
+        assert_eq!(corrected_extension(&dir.join("c")).as_deref(), Some("jpg"));
+        assert_eq!(corrected_extension(&dir.join("d.txt")), None);
+        // 识别为 TIFF 的文件只修正明显错误的扩展名，基于 TIFF 的 RAW 保持原样
+        assert_eq!(corrected_extension(&dir.join("e.NRW")), None);
+        assert_eq!(corrected_extension(&dir.join("f")), None);
+        assert_eq!(corrected_extension(&dir.join("g.jpg")).as_deref(), Some("tif"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,244 @@
+//! 模板语法：字面量与 `{name}` / `{name:arg}` 占位符，`{{` `}}` 表示字面量花括号
+
+use anyhow::Result;
+use std::fmt;
+
+/// 模板片段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Literal(String),
+    Placeholder { name: String, arg: Option<String> },
+}
+
+/// 解析后的模板
+#[derive(Debug, Clone)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.char_indices().peekable();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|&(_, c)| c) == Some('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek().map(|&(_, c)| c) == Some('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut body = String::new();
+                    let mut closed = false;
+                    for (_, c) in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        body.push(c);
+                    }
+                    if !closed {
+                        anyhow::bail!("模板 `{}` 第 {} 个字符处的 `{{` 没有闭合", source, i + 1);
+                    }
+                    let (name, arg) = match body.split_once(':') {
+                        Some((name, arg)) => (name.trim(), Some(arg.to_string())),
+                        None => (body.trim(), None),
+                    };
+                    if name.is_empty() {
+                        anyhow::bail!("模板 `{}` 第 {} 个字符处的占位符为空", source, i + 1);
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder {
+                        name: name.to_string(),
+                        arg,
+                    });
+                }
+                '}' => anyhow::bail!("模板 `{}` 第 {} 个字符处有多余的 `}}`", source, i + 1),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Template {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// 是否使用了指定占位符
+    pub fn uses(&self, name: &str) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Placeholder { name: n, .. } if n == name))
+    }
+
+    /// 检查占位符名称是否都在允许列表中
+    pub fn check_names(&self, allowed: &[&str]) -> Result<()> {
+        for segment in &self.segments {
+            if let Segment::Placeholder { name, .. } = segment {
+                if !allowed.contains(&name.as_str()) {
+                    anyhow::bail!(
+                        "模板 `{}` 中有未知占位符 {{{}}}（可用: {}）",
+                        self.source,
+                        name,
+                        allowed.join(", ")
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 渲染模板，占位符的值由 `value(name, arg)` 提供
+    pub fn render(&self, mut value: impl FnMut(&str, Option<&str>) -> Result<String>) -> Result<String> {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => out.push_str(s),
+                Segment::Placeholder { name, arg } => out.push_str(&value(name, arg.as_deref())?),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// 将元数据值清理为可用于文件名的片段
+pub fn sanitize(value: &str) -> String {
+    let cleaned: String = value
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim_matches('.').trim().to_string();
+    if cleaned.is_empty() {
+        "unknown".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// 检查 strftime 格式串能否用于格式化本地时间，错误信息指出出错的格式符
+pub fn validate_strftime(fmt: &str) -> Result<()> {
+    use chrono::NaiveDate;
+    use std::fmt::Write;
+
+    let sample = NaiveDate::from_ymd_opt(2000, 1, 1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .expect("合法日期");
+    let formats = |spec: &str| write!(String::new(), "{}", sample.format(spec)).is_ok();
+
+    let mut chars = fmt.char_indices().peekable();
+    let mut position = 0;
+    while let Some((i, c)) = chars.next() {
+        position += 1;
+        if c != '%' {
+            continue;
+        }
+        // 格式符：% [填充/宽度/精度修饰] 字母
+        let start = i;
+        let mut end = fmt.len();
+        while let Some(&(j, c)) = chars.peek() {
+            chars.next();
+            if !matches!(c, '-' | '_' | '0'..='9' | '#' | ':' | '.') {
+                end = j + c.len_utf8();
+                break;
+            }
+        }
+        let spec = &fmt[start..end];
+        if spec == "%" {
+            anyhow::bail!("日期格式 `{}` 第 {} 个字符处的 `%` 后缺少格式符", fmt, position);
+        }
+        if !formats(spec) {
+            anyhow::bail!(
+                "日期格式 `{}` 第 {} 个字符处的 `{}` 不是有效的格式符（时区格式符 %z %Z %+ 也不可用）",
+                fmt,
+                position,
+                spec
+            );
+        }
+        position += spec.chars().count() - 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholder(name: &str, arg: Option<&str>) -> Segment {
+        Segment::Placeholder {
+            name: name.to_string(),
+            arg: arg.map(String::from),
+        }
+    }
+
+    #[test]
+    fn parses_segments() {
+        let template = Template::parse("{{x}}_{date:%Y-%m}/{ place }").unwrap();
+        assert_eq!(
+            template.segments(),
+            [
+                Segment::Literal("{x}_".to_string()),
+                placeholder("date", Some("%Y-%m")),
+                Segment::Literal("/".to_string()),
+                placeholder("place", None),
+            ]
+        );
+        assert!(template.uses("place") && !template.uses("x"));
+        assert_eq!(template.to_string(), "{{x}}_{date:%Y-%m}/{ place }");
+        assert!(template.check_names(&["date", "place"]).is_ok());
+        assert!(template.check_names(&["date"]).is_err());
+
+        let rendered = template.render(|name, arg| Ok(format!("<{}:{:?}>", name, arg)));
+        assert_eq!(rendered.unwrap(), "{x}_<date:Some(\"%Y-%m\")>/<place:None>");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        for source in ["{date", "a}", "{}", "{:x}", "x{y}}"] {
+            assert!(Template::parse(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn sanitizes_values() {
+        assert_eq!(sanitize(" Canon EOS R5 "), "Canon EOS R5");
+        assert_eq!(sanitize("a/b\\c:d*?"), "a_b_c_d__");
+        assert_eq!(sanitize(".."), "unknown");
+        assert_eq!(sanitize("\n"), "unknown");
+    }
+
+    #[test]
+    fn validates_strftime() {
+        assert!(validate_strftime("%Y/%m/%d %-H%%").is_ok());
+        for invalid in ["%Q", "%Y-%", "%z", "%Z", "%+"] {
+            assert!(validate_strftime(invalid).is_err(), "{}", invalid);
+        }
+        let error = validate_strftime("%Y-%m-%Q").unwrap_err().to_string();
+        assert!(error.contains("第 7 个字符"), "{}", error);
+    }
+}
@@ -0,0 +1,192 @@
+//! 相机时钟校正（`--time-shift` 与配置文件的 `[time_shift]` 表）
+//!
+//! 规则形如 `[相机=]偏移`：相机可以是机身序列号、"品牌 型号"、型号或品牌（不区分大小写），
+//! 省略或写作 `*` 时对所有文件生效；偏移为 `±HH:MM[:SS]`。
+
+use crate::date::{CaptureDate, DateSource};
+use crate::metadata::Metadata;
+use anyhow::Result;
+use chrono::TimeDelta;
+use std::fmt;
+use std::str::FromStr;
+
+/// 时钟偏移量
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeShift(pub TimeDelta);
+
+impl FromStr for TimeShift {
+    type Err = String;
+
+    /// 解析 `+01:00:30`、`-00:05`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("无效的时间偏移: {}（格式: ±HH:MM[:SS]，如 +01:00:30）", s);
+        let s = s.trim();
+        let (sign, rest) = match s.chars().next() {
+            Some('+') => (1, &s[1..]),
+            Some('-') => (-1, &s[1..]),
+            _ => return Err(invalid()),
+        };
+        let parts: Vec<&str> = rest.split(':').collect();
+        if !(2..=3).contains(&parts.len()) || parts.iter().any(|p| p.is_empty()) {
+            return Err(invalid());
+        }
+        let numbers: Vec<i64> = parts
+            .iter()
+            .map(|p| p.parse::<u32>().map(i64::from))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+        let (hours, minutes) = (numbers[0], numbers[1]);
+        let seconds = numbers.get(2).copied().unwrap_or(0);
+        if minutes >= 60 || seconds >= 60 {
+            return Err(invalid());
+        }
+        Ok(TimeShift(TimeDelta::seconds(
+            sign * (hours * 3600 + minutes * 60 + seconds),
+        )))
+    }
+}
+
+impl fmt::Display for TimeShift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.num_seconds();
+        let sign = if secs < 0 { '-' } else { '+' };
+        let secs = secs.abs();
+        write!(f, "{}{:02}:{:02}:{:02}", sign, secs / 3600, secs % 3600 / 60, secs % 60)
+    }
+}
+
+/// 一条校正规则
+#[derive(Debug, Clone)]
+pub struct ShiftRule {
+    /// 匹配的相机；`None` 表示所有文件
+    pub camera: Option<String>,
+    pub shift: TimeShift,
+}
+
+impl ShiftRule {
+    pub fn new(camera: &str, shift: TimeShift) -> Self {
+        let camera = camera.trim();
+        ShiftRule {
+            camera: (!camera.is_empty() && camera != "*").then(|| camera.to_string()),
+            shift,
+        }
+    }
+}
+
+impl FromStr for ShiftRule {
+    type Err = String;
+
+    /// 解析 `[相机=]偏移`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.rsplit_once('=') {
+            Some((camera, shift)) => Ok(ShiftRule::new(camera, shift.parse()?)),
+            None => Ok(ShiftRule::new("", s.parse()?)),
+        }
+    }
+}
+
+/// 所有校正规则：序列号优先，其次 "品牌 型号"、型号、品牌，最后是通用规则；
+/// 同一级别中后加入的规则（命令行）覆盖先加入的（配置文件）
+#[derive(Debug, Clone, Default)]
+pub struct TimeShifts {
+    rules: Vec<ShiftRule>,
+}
+
+impl TimeShifts {
+    pub fn new(rules: impl IntoIterator<Item = ShiftRule>) -> Self {
+        TimeShifts {
+            rules: rules.into_iter().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// 是否有针对特定相机的规则（需要读取相机信息）
+    pub fn needs_metadata(&self) -> bool {
+        self.rules.iter().any(|rule| rule.camera.is_some())
+    }
+
+    /// 校正相机自己写入的日期（EXIF/视频元数据），返回实际应用的偏移
+    pub fn apply(
+        &self,
+        extracted: &mut Option<(CaptureDate, DateSource)>,
+        metadata: &Metadata,
+    ) -> Result<Option<TimeShift>> {
+        let Some((date, DateSource::Exif)) = extracted else {
+            return Ok(None);
+        };
+        let Some(shift) = self.find(metadata).filter(|s| !s.0.is_zero()) else {
+            return Ok(None);
+        };
+        *date = date.shifted(shift.0)?;
+        Ok(Some(shift))
+    }
+
+    /// 查找适用于该相机的偏移
+    pub fn find(&self, metadata: &Metadata) -> Option<TimeShift> {
+        let make_model = match (&metadata.make, &metadata.model) {
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            _ => None,
+        };
+        let candidates = [
+            metadata.serial.as_deref(),
+            make_model.as_deref(),
+            metadata.model.as_deref(),
+            metadata.make.as_deref(),
+        ];
+
+        for candidate in candidates.into_iter().flatten() {
+            let matched = self.rules.iter().rev().find(|rule| {
+                rule.camera
+                    .as_deref()
+                    .is_some_and(|camera| camera.eq_ignore_ascii_case(candidate))
+            });
+            if let Some(rule) = matched {
+                return Some(rule.shift);
+            }
+        }
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.camera.is_none())
+            .map(|rule| rule.shift)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_offsets() {
+        let shift: TimeShift = "+01:00:30".parse().unwrap();
+        assert_eq!(shift.0, TimeDelta::seconds(3630));
+        assert_eq!(shift.to_string(), "+01:00:30");
+        assert_eq!("-00:05".parse::<TimeShift>().unwrap().0, TimeDelta::minutes(-5));
+        assert!("01:00".parse::<TimeShift>().is_err());
+        assert!("+01:60".parse::<TimeShift>().is_err());
+    }
+
+    #[test]
+    fn most_specific_rule_wins() {
+        let rules = [
+            "+00:00:10",
+            "Canon=+00:01",
+            "EOS 5D=+00:02",
+            "serial123=+00:03",
+            "canon eos 5d=+00:04",
+        ];
+        let shifts = TimeShifts::new(rules.iter().map(|s| s.parse().unwrap()));
+        let mut metadata = Metadata {
+            make: Some("Canon".to_string()),
+            model: Some("EOS 5D".to_string()),
+            ..Metadata::default()
+        };
+        assert_eq!(shifts.find(&metadata).unwrap().to_string(), "+00:04:00");
+        metadata.serial = Some("SERIAL123".to_string());
+        assert_eq!(shifts.find(&metadata).unwrap().to_string(), "+00:03:00");
+        assert_eq!(shifts.find(&Metadata::default()).unwrap().to_string(), "+00:00:10");
+    }
+}
@@ -0,0 +1,331 @@
+//! 配置文件使用的 TOML 子集解析
+//!
+//! 支持 `[表]` / `[a.b]` 表头、裸键与带引号的键、基本字符串与字面量字符串、
+//! 整数、浮点数、布尔值、数组以及 `#` 注释；不支持表数组、内联表与多行字符串。
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// 表：键按字母顺序排列
+pub type Table = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    /// 值的类型名，用于错误信息
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "字符串",
+            Value::Integer(_) => "整数",
+            Value::Float(_) => "浮点数",
+            Value::Boolean(_) => "布尔值",
+            Value::Array(_) => "数组",
+            Value::Table(_) => "表",
+        }
+    }
+}
+
+/// 解析错误（行号从 1 开始）
+#[derive(Debug, Clone)]
+pub struct TomlError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TomlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "第 {} 行: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TomlError {}
+
+/// 解析 TOML 文本
+pub fn parse(text: &str) -> Result<Table, TomlError> {
+    let mut root = Table::new();
+    let mut current: Vec<String> = Vec::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line_no = index + 1;
+        let err = |message: String| TomlError {
+            line: line_no,
+            message,
+        };
+        let mut p = Parser::new(raw);
+        p.skip_ws();
+        if p.at_end_of_line() {
+            continue;
+        }
+
+        if p.eat('[') {
+            if p.peek() == Some('[') {
+                return Err(err("不支持表数组 [[...]]".to_string()));
+            }
+            let path = p.key_path().map_err(&err)?;
+            p.skip_ws();
+            if !p.eat(']') {
+                return Err(err("表头缺少 `]`".to_string()));
+            }
+            p.skip_ws();
+            if !p.at_end_of_line() {
+                return Err(err("表头后有多余内容".to_string()));
+            }
+            table_at(&mut root, &path).map_err(&err)?;
+            current = path;
+            continue;
+        }
+
+        let path = p.key_path().map_err(&err)?;
+        p.skip_ws();
+        if !p.eat('=') {
+            return Err(err(format!("键 `{}` 后缺少 `=`", path.join("."))));
+        }
+        p.skip_ws();
+        let value = p.value().map_err(&err)?;
+        p.skip_ws();
+        if !p.at_end_of_line() {
+            return Err(err("值后有多余内容".to_string()));
+        }
+
+        let (last, parents) = path.split_last().expect("键路径非空");
+        let full: Vec<String> = current.iter().chain(parents).cloned().collect();
+        let table = table_at(&mut root, &full).map_err(&err)?;
+        if table.contains_key(last) {
+            return Err(err(format!("键 `{}` 重复定义", path.join("."))));
+        }
+        table.insert(last.clone(), value);
+    }
+
+    Ok(root)
+}
+
+/// 取得（必要时创建）路径对应的表
+fn table_at<'t>(root: &'t mut Table, path: &[String]) -> Result<&'t mut Table, String> {
+    let mut table = root;
+    for key in path {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match entry {
+            Value::Table(t) => t,
+            other => return Err(format!("`{}` 已定义为{}，不能作为表", key, other.type_name())),
+        };
+    }
+    Ok(table)
+}
+
+/// 单行解析器
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(line: &'a str) -> Self {
+        Parser {
+            chars: line.chars().peekable(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.chars.next();
+        }
+    }
+
+    fn at_end_of_line(&mut self) -> bool {
+        matches!(self.peek(), None | Some('#'))
+    }
+
+    /// 以 `.` 分隔的键路径
+    fn key_path(&mut self) -> Result<Vec<String>, String> {
+        let mut path = Vec::new();
+        loop {
+            self.skip_ws();
+            path.push(self.key()?);
+            self.skip_ws();
+            if !self.eat('.') {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        break;
+                    }
+                    key.push(c);
+                    self.chars.next();
+                }
+                if key.is_empty() {
+                    return Err("缺少键名".to_string());
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some(_) => self.scalar(),
+            None => Err("缺少值".to_string()),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.chars.next();
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_ws();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(',') {
+                return Err("数组元素之间缺少 `,`".to_string());
+            }
+        }
+    }
+
+    /// 布尔值或数字
+    fn scalar(&mut self) -> Result<Value, String> {
+        let mut token = String::new();
+        while let Some(c) = self.peek() {
+            if matches!(c, ' ' | '\t' | ',' | ']' | '#') {
+                break;
+            }
+            token.push(c);
+            self.chars.next();
+        }
+        match token.as_str() {
+            "true" => return Ok(Value::Boolean(true)),
+            "false" => return Ok(Value::Boolean(false)),
+            _ => {}
+        }
+        let digits = token.replace('_', "");
+        if let Ok(n) = digits.parse::<i64>() {
+            return Ok(Value::Integer(n));
+        }
+        if digits.contains(['.', 'e', 'E']) {
+            if let Ok(f) = digits.parse::<f64>() {
+                return Ok(Value::Float(f));
+            }
+        }
+        Err(format!("无法识别的值 `{}`（字符串需要加引号）", token))
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.chars.next();
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err("字符串缺少结尾的 `\"`".to_string()),
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let c = match self.chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(c @ ('u' | 'U')) => {
+                            let len = if c == 'u' { 4 } else { 8 };
+                            let hex: String = self.chars.by_ref().take(len).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| format!("无效的 Unicode 转义 \\{}{}", c, hex))?
+                        }
+                        Some(c) => return Err(format!("无效的转义 \\{}", c)),
+                        None => return Err("字符串缺少结尾的 `\"`".to_string()),
+                    };
+                    out.push(c);
+                }
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.chars.next();
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err("字符串缺少结尾的 `'`".to_string()),
+                Some('\'') => return Ok(out),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tables_and_values() {
+        let table = parse(
+            r#"
+# 注释
+output = "~/Sorted"   # 行尾注释
+jobs = 4
+dedup = true
+fallback = ["exif", 'filename']
+
+[time_shift]
+"Canon EOS 5D" = "+01:00:30"
+serial.a = 'C:\raw'
+"#,
+        )
+        .unwrap();
+        assert_eq!(table["output"], Value::String("~/Sorted".to_string()));
+        assert_eq!(table["jobs"], Value::Integer(4));
+        assert_eq!(table["dedup"], Value::Boolean(true));
+        let Value::Table(shift) = &table["time_shift"] else { panic!() };
+        assert_eq!(shift["Canon EOS 5D"], Value::String("+01:00:30".to_string()));
+        let Value::Table(serial) = &shift["serial"] else { panic!() };
+        assert_eq!(serial["a"], Value::String(r"C:\raw".to_string()));
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let err = parse("a = 1\nb = oops\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(parse("a = 1\na = 2").is_err());
+        assert!(parse("a = \"open").is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! 单个文件的传输：复制、移动、硬链接、符号链接或克隆（reflink）
+//!
+//! 需要写入内容的传输先写到目标目录中的 `.文件名.porg-tmp`，完成后再重命名为最终文件名，
+//! 中断时不会在最终文件名处留下不完整的文件。
+
+use crate::hash::{hash_file, Digest};
+use crate::journal::Operation;
+use crate::preserve::{self, Preserve, Preserved};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// 临时文件后缀
+const TEMP_SUFFIX: &str = ".porg-tmp";
+
+/// 文件传输方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    /// 复制文件
+    Copy,
+    /// 移动文件（跨设备时复制后删除源文件）
+    Move,
+    /// 创建硬链接，不占用额外空间（须与源文件在同一文件系统）
+    Hardlink,
+    /// 创建指向源文件的符号链接
+    Symlink,
+    /// 写时复制克隆（btrfs、XFS 等），不支持时退回普通复制
+    Reflink,
+}
+
+impl Mode {
+    /// 输出中使用的动作名称
+    pub fn describe(self) -> &'static str {
+        match self {
+            Mode::Copy => "复制",
+            Mode::Move => "移动",
+            Mode::Hardlink => "硬链接",
+            Mode::Symlink => "符号链接",
+            Mode::Reflink => "克隆",
+        }
+    }
+}
+
+/// 传输选项
+#[derive(Debug, Clone, Copy)]
+pub struct TransferOptions {
+    pub mode: Mode,
+    /// 移动需要复制时（跨设备），比对源文件与副本的摘要，一致才删除源文件
+    pub verify: bool,
+    /// 重命名为最终文件名之前把内容写入磁盘
+    pub fsync: bool,
+    /// 写入内容后（重命名之前）保留的源文件属性
+    pub preserve: Preserve,
+}
+
+/// 一次传输的结果
+pub struct Transferred {
+    /// 实际执行的操作（克隆不可用时为复制）
+    pub operation: Operation,
+    /// 校验时已计算出的摘要
+    pub digest: Option<Digest>,
+    /// 目标文件系统不支持扩展属性，未能保留
+    pub xattrs_unsupported: bool,
+}
+
+/// 把 `source` 传输到 `target`
+pub fn transfer(options: &TransferOptions, source: &Path, target: &Path) -> Result<Transferred> {
+    let transferred = transfer_file(options, source, target)?;
+    if options.fsync {
+        if let Some(dir) = target.parent() {
+            sync_dir(dir).with_context(|| format!("无法同步目录: {}", dir.display()))?;
+        }
+    }
+    Ok(transferred)
+}
+
+fn transfer_file(options: &TransferOptions, source: &Path, target: &Path) -> Result<Transferred> {
+    let copy = || {
+        write_atomic(options, source, target, |tmp| fs::copy(source, tmp).map(|_| ()))
+            .with_context(|| format!("无法复制: {} → {}", source.display(), target.display()))
+    };
+    let done = |operation, preserved: Preserved| {
+        Ok(Transferred {
+            operation,
+            digest: None,
+            xattrs_unsupported: preserved.xattrs_unsupported,
+        })
+    };
+    match options.mode {
+        Mode::Copy => done(Operation::Copy, copy()?),
+        Mode::Move => {
+            if fs::rename(source, target).is_ok() {
+                return done(Operation::Move, Preserved::default());
+            }
+            let preserved = copy()?;
+            let digest = if options.verify {
+                Some(verify_copy(source, target)?)
+            } else {
+                None
+            };
+            fs::remove_file(source)
+                .with_context(|| format!("无法删除源文件: {}", source.display()))?;
+            Ok(Transferred {
+                operation: Operation::Move,
+                digest,
+                xattrs_unsupported: preserved.xattrs_unsupported,
+            })
+        }
+        Mode::Hardlink => {
+            fs::hard_link(source, target).with_context(|| {
+                format!(
+                    "无法创建硬链接（输出目录须与源文件在同一文件系统）: {} → {}",
+                    source.display(),
+                    target.display()
+                )
+            })?;
+            done(Operation::Hardlink, Preserved::default())
+        }
+        Mode::Symlink => {
+            symlink(source, target).with_context(|| {
+                format!("无法创建符号链接: {} → {}", target.display(), source.display())
+            })?;
+            done(Operation::Symlink, Preserved::default())
+        }
+        Mode::Reflink => {
+            let mut cloned = false;
+            let preserved = write_atomic(options, source, target, |tmp| {
+                cloned = reflink(source, tmp)?;
+                Ok(())
+            })
+            .with_context(|| format!("无法克隆: {} → {}", source.display(), target.display()))?;
+            if cloned {
+                done(Operation::Reflink, preserved)
+            } else {
+                done(Operation::Copy, copy()?)
+            }
+        }
+    }
+}
+
+/// 临时文件路径：与目标同目录的 `.文件名.porg-tmp`
+pub fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}{}", name, TEMP_SUFFIX))
+}
+
+/// 由 `write` 写出临时文件，保留源文件属性后重命名为 `target`；失败时删除临时文件。
+/// `write` 没有创建临时文件（如克隆不可用）时不做任何事
+fn write_atomic(
+    options: &TransferOptions,
+    source: &Path,
+    target: &Path,
+    write: impl FnOnce(&Path) -> io::Result<()>,
+) -> io::Result<Preserved> {
+    let tmp = temp_path(target);
+    let result = write(&tmp).and_then(|()| {
+        if !tmp.exists() {
+            return Ok(Preserved::default());
+        }
+        let preserved = preserve::apply(source, &tmp, options.preserve)?;
+        if options.fsync {
+            fs::File::open(&tmp)?.sync_all()?;
+        }
+        fs::rename(&tmp, target)?;
+        Ok(preserved)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// 把目录项（重命名结果）写入磁盘
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// 删除目录下中断的运行遗留的临时文件，返回删除的数量
+pub fn remove_stale_temps(dir: &Path) -> usize {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && is_temp(e.path()))
+        .filter(|e| fs::remove_file(e.path()).is_ok())
+        .count()
+}
+
+/// 是否为 porg 的临时文件
+pub fn is_temp(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.') && n.ends_with(TEMP_SUFFIX))
+}
+
+/// 比对源文件与副本；不一致时删除副本并报错，源文件保持不动
+fn verify_copy(source: &Path, target: &Path) -> Result<Digest> {
+    let expected = hash_file(source)?;
+    let actual = hash_file(target)?;
+    if actual != expected {
+        let _ = fs::remove_file(target);
+        anyhow::bail!(
+            "校验失败，副本与源文件内容不一致（已删除副本，保留源文件）: {} → {}",
+            source.display(),
+            target.display()
+        );
+    }
+    Ok(actual)
+}
+
+#[cfg(unix)]
+fn symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(windows)]
+fn symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(source, target)
+}
+
+/// 用 FICLONE 克隆文件内容；文件系统不支持时删除已创建的空文件并返回 `Ok(false)`
+#[cfg(target_os = "linux")]
+fn reflink(source: &Path, target: &Path) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+    use std::os::raw::{c_int, c_ulong};
+
+    /// `_IOW(0x94, 9, int)`
+    const FICLONE: c_ulong = 0x4004_9409;
+
+    extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    let src = fs::File::open(source)?;
+    let dst = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(target)?;
+    // SAFETY: 两个文件描述符在调用期间都有效，FICLONE 的参数是源文件描述符
+    let ret = unsafe { ioctl(dst.as_raw_fd(), FICLONE, src.as_raw_fd()) };
+    if ret == -1 {
+        // EOPNOTSUPP、EXDEV、EINVAL 等：文件系统不支持或跨设备
+        drop(dst);
+        fs::remove_file(target)?;
+        return Ok(false);
+    }
+    // 与 fs::copy 一致，保留权限位
+    dst.set_permissions(src.metadata()?.permissions())?;
+    Ok(true)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_source: &Path, _target: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atomic_copy_leaves_no_temporary() {
+        let dir = std::env::temp_dir().join(format!("porg-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, target) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&source, b"original").unwrap();
+        fs::write(temp_path(&target), b"stale").unwrap();
+        assert_eq!(remove_stale_temps(&dir), 1);
+
+        let options = TransferOptions {
+            mode: Mode::Reflink,
+            verify: true,
+            fsync: true,
+            preserve: Preserve::all(),
+        };
+        transfer(&options, &source, &target).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"original");
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+        assert_eq!(modified(&target), modified(&source));
+        assert!(!temp_path(&target).exists());
+
+        let missing = dir.join("missing.jpg");
+        assert!(transfer(&options, &missing, &dir.join("c.jpg")).is_err());
+        assert_eq!(remove_stale_temps(&dir), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mismatched_copy_is_removed_and_source_kept() {
+        let dir = std::env::temp_dir().join(format!("porg-verify-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (source, target) = (dir.join("a.jpg"), dir.join("b.jpg"));
+        fs::write(&source, b"original").unwrap();
+        fs::write(&target, b"corrupt!").unwrap();
+        assert!(verify_copy(&source, &target).is_err());
+        assert!(source.exists() && !target.exists());
+
+        fs::copy(&source, &target).unwrap();
+        assert_eq!(verify_copy(&source, &target).unwrap(), hash_file(&source).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
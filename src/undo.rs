@@ -0,0 +1,207 @@
+//! `porg undo`：按操作日志撤销一次运行
+
+use crate::hash::hash_file;
+use crate::journal::{self, Entry, Operation};
+use crate::preserve::Preserve;
+use crate::transfer::{self, Mode, TransferOptions};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// 撤销统计
+#[derive(Default)]
+pub struct UndoStats {
+    pub restored: usize,
+    pub deleted: usize,
+    pub kept: usize,
+    pub errors: usize,
+}
+
+/// 撤销一次运行：移动的文件放回原处，未被修改过的副本、链接与克隆删除
+pub fn undo(output_dir: &Path, run_id: Option<&str>, dry_run: bool, quiet: bool) -> Result<UndoStats> {
+    let path = journal::find(output_dir, run_id)?;
+    let entries = journal::read(&path)?;
+
+    if !quiet {
+        if dry_run {
+            println!("🔍 预览模式 — 不会实际操作文件\n");
+        }
+        println!("🧾 撤销运行: {}", journal::run_id_of(&path));
+        if let Some(first) = entries.first() {
+            println!("🕒 运行时间: {}", first.timestamp);
+        }
+        println!("📄 共 {} 条记录\n", entries.len());
+    }
+
+    let mut stats = UndoStats::default();
+
+    // 倒序撤销，保证同一文件的多次操作按相反顺序恢复
+    for entry in entries.iter().rev() {
+        if let Err(e) = undo_entry(entry, output_dir, dry_run, quiet, &mut stats) {
+            stats.errors += 1;
+            eprintln!("⚠️  撤销失败: {} — {}", entry.destination.display(), e);
+        }
+    }
+
+    // 全部成功才标记为已撤销，否则保留日志以便重试
+    if !dry_run && stats.errors == 0 {
+        journal::mark_undone(&path)?;
+    }
+
+    Ok(stats)
+}
+
+fn undo_entry(
+    entry: &Entry,
+    output_dir: &Path,
+    dry_run: bool,
+    quiet: bool,
+    stats: &mut UndoStats,
+) -> Result<()> {
+    let dest = &entry.destination;
+    let source = &entry.source;
+
+    // 源文件已删除时符号链接悬空，exists() 会返回 false
+    let exists = match entry.operation {
+        Operation::Symlink => dest.symlink_metadata().is_ok(),
+        _ => dest.exists(),
+    };
+    if !exists {
+        if !quiet {
+            println!("  跳过: {} 已不存在", dest.display());
+        }
+        stats.kept += 1;
+        return Ok(());
+    }
+
+    match entry.operation {
+        Operation::Move => {
+            if source.exists() {
+                if !quiet {
+                    println!("  保留: {} — 原位置已有文件 {}", dest.display(), source.display());
+                }
+                stats.kept += 1;
+                return Ok(());
+            }
+            if !quiet {
+                println!(
+                    "  {} {} → {}",
+                    if dry_run { "[预览还原]" } else { "还原:" },
+                    dest.display(),
+                    source.display()
+                );
+            }
+            if !dry_run {
+                if let Some(parent) = source.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("无法创建目录: {}", parent.display()))?;
+                }
+                let options = TransferOptions {
+                    mode: Mode::Move,
+                    verify: true,
+                    fsync: false,
+                    preserve: Preserve::all(),
+                };
+                transfer::transfer(&options, dest, source)?;
+                remove_empty_parents(dest, output_dir);
+            }
+            stats.restored += 1;
+        }
+        Operation::Copy | Operation::Hardlink | Operation::Symlink | Operation::Reflink => {
+            // 符号链接只要仍指向原文件就可以删除，其余按内容判断是否被修改；
+            // 没有记录摘要的链接与克隆在内容仍与源文件相同时删除
+            let unchanged = match (entry.operation, entry.digest) {
+                (Operation::Symlink, _) => fs::read_link(dest).is_ok_and(|t| t == *source),
+                (_, Some(digest)) => hash_file(dest)? == digest,
+                (_, None) => source.exists() && hash_file(dest)? == hash_file(source)?,
+            };
+            if !unchanged {
+                if !quiet {
+                    println!("  保留: {} — 整理后已被修改", dest.display());
+                }
+                stats.kept += 1;
+                return Ok(());
+            }
+            if !quiet {
+                println!(
+                    "  {} {}",
+                    if dry_run { "[预览删除]" } else { "删除:" },
+                    dest.display()
+                );
+            }
+            if !dry_run {
+                fs::remove_file(dest)
+                    .with_context(|| format!("无法删除文件: {}", dest.display()))?;
+                remove_empty_parents(dest, output_dir);
+            }
+            stats.deleted += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// 删除撤销后变空的日期目录（不超出输出目录）
+fn remove_empty_parents(path: &Path, output_dir: &Path) {
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d == output_dir || !d.starts_with(output_dir) || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Journal;
+
+    #[test]
+    fn undoes_run() {
+        let dir = std::env::temp_dir().join(format!("porg-undo-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let (src, out) = (dir.join("src"), dir.join("out"));
+        fs::create_dir_all(out.join("2024/01")).unwrap();
+        fs::create_dir_all(&src).unwrap();
+
+        // 复制后未修改、复制后被修改、移动
+        let mut journal = Journal::new(&out);
+        let mut record = |operation, name: &str, content: &[u8]| {
+            let (source, dest) = (src.join(name), out.join("2024/01").join(name));
+            fs::write(&dest, content).unwrap();
+            if operation == Operation::Copy {
+                fs::write(&source, content).unwrap();
+            }
+            journal.record(operation, &source, &dest, Some(hash_file(&dest).unwrap())).unwrap();
+            (source, dest)
+        };
+        let (copied_src, copied) = record(Operation::Copy, "a.jpg", b"a");
+        let (_, edited) = record(Operation::Copy, "b.jpg", b"b");
+        let (moved_src, moved) = record(Operation::Move, "c.jpg", b"c");
+        fs::write(&edited, b"edited").unwrap();
+        // 没有记录摘要的链接与克隆：仍与源文件相同时删除，源文件已不存在时保留
+        let (linked_src, linked) = (src.join("d.jpg"), out.join("2024/01/d.jpg"));
+        fs::write(&linked_src, b"d").unwrap();
+        fs::hard_link(&linked_src, &linked).unwrap();
+        journal.record(Operation::Hardlink, &linked_src, &linked, None).unwrap();
+        let orphan = out.join("2024/01/e.jpg");
+        fs::write(&orphan, b"e").unwrap();
+        journal.record(Operation::Reflink, &src.join("e.jpg"), &orphan, None).unwrap();
+
+        let stats = undo(&out, None, true, true).unwrap();
+        assert_eq!((stats.restored, stats.deleted, stats.kept), (1, 2, 2));
+        assert!(copied.exists() && moved.exists());
+
+        let stats = undo(&out, None, false, true).unwrap();
+        assert_eq!((stats.restored, stats.deleted, stats.kept, stats.errors), (1, 2, 2, 0));
+        assert!(!copied.exists() && copied_src.exists());
+        assert!(!linked.exists() && linked_src.exists() && orphan.exists());
+        assert_eq!(fs::read(&moved_src).unwrap(), b"c");
+        assert!(!moved.exists());
+        assert_eq!(fs::read(&edited).unwrap(), b"edited");
+        // 已撤销的运行不会被再次撤销
+        assert!(undo(&out, None, false, true).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! 从 QuickTime/MP4（ISO-BMFF）容器读取视频创建时间
+
+use crate::date::CaptureDate;
+use crate::media;
+use crate::sniff;
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, Utc};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// 支持的视频文件扩展名
+pub const VIDEO_EXTENSIONS: &[&str] = &["mov", "mp4", "m4v", "3gp"];
+
+/// QuickTime 纪元（1904-01-01）与 Unix 纪元之间的秒数
+const MAC_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Apple 设备写入的本地拍摄时间（带时区）
+const APPLE_CREATION_DATE_KEY: &str = "com.apple.quicktime.creationdate";
+
+/// Live Photo 视频与照片共享的内容标识
+pub const APPLE_CONTENT_ID_KEY: &str = "com.apple.quicktime.content.identifier";
+
+/// moov 盒子的读取上限，避免损坏文件导致超大内存分配
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Apple 创建时间的常见格式
+const APPLE_DATE_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%z", "%Y-%m-%dT%H:%M:%S%.f%z"];
+
+/// 判断文件是否是支持的视频格式：优先按文件内容，无法识别时按扩展名
+pub fn is_video(path: &Path) -> bool {
+    match sniff::detect(path) {
+        Some(format) => format.is_video(),
+        None => has_video_extension(path),
+    }
+}
+
+/// 扩展名是否是支持的视频格式
+pub fn has_video_extension(path: &Path) -> bool {
+    media::has_extension(path, VIDEO_EXTENSIONS)
+}
+
+/// 读取视频的拍摄时间
+///
+/// 优先使用 Apple 元数据中带时区的本地时间，其次使用 `mvhd` 中的 UTC 创建时间。
+/// `mvhd` 不记录拍摄地时区，因此保持为 UTC，由 `--tz` 决定是否换算。
+pub fn extract_creation_date(path: &Path) -> Result<Option<CaptureDate>> {
+    let Some(moov) = read_moov(path)? else {
+        return Ok(None);
+    };
+
+    if let Some(value) = find_apple_metadata(&moov, APPLE_CREATION_DATE_KEY) {
+        if let Some(dt) = parse_apple_date(&value) {
+            return Ok(Some(CaptureDate::Zoned(dt)));
+        }
+    }
+
+    Ok(find_box(&moov, b"mvhd")
+        .and_then(parse_mvhd_creation_time)
+        .map(|utc| CaptureDate::Zoned(utc.fixed_offset())))
+}
+
+/// 读取 moov/meta 中的一项 Apple 元数据
+pub fn read_apple_metadata(path: &Path, key: &str) -> Result<Option<String>> {
+    Ok(read_moov(path)?.and_then(|moov| find_apple_metadata(&moov, key)))
+}
+
+/// 读取顶层 moov 盒子的内容
+fn read_moov(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut file = fs::File::open(path).context("无法打开文件")?;
+    let file_len = file.metadata().context("无法读取文件信息")?.len();
+    let mut pos = 0u64;
+
+    while file_len.saturating_sub(pos) >= 8 {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let box_type = [header[4], header[5], header[6], header[7]];
+        let mut header_len = 8u64;
+
+        if size == 1 {
+            file.read_exact(&mut header[8..16])?;
+            size = u64::from_be_bytes(header[8..16].try_into()?);
+            header_len = 16;
+        } else if size == 0 {
+            size = file_len - pos;
+        }
+        if size < header_len {
+            return Ok(None);
+        }
+
+        if &box_type == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_SIZE {
+                anyhow::bail!("moov 盒子过大: {} 字节", body_len);
+            }
+            let mut body = vec![0u8; body_len as usize];
+            file.read_exact(&mut body).context("moov 盒子不完整")?;
+            return Ok(Some(body));
+        }
+        // 损坏的盒子大小可能使位置溢出
+        let Some(next) = pos.checked_add(size) else {
+            return Ok(None);
+        };
+        pos = next;
+    }
+
+    Ok(None)
+}
+
+/// 遍历一段数据中的子盒子，返回 (类型, 内容)
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let mut size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let box_type = [data[4], data[5], data[6], data[7]];
+        let mut header_len = 8;
+        if size == 1 {
+            if data.len() < 16 {
+                return None;
+            }
+            size = u64::from_be_bytes(data[8..16].try_into().ok()?) as usize;
+            header_len = 16;
+        } else if size == 0 {
+            size = data.len();
+        }
+        if size < header_len || size > data.len() {
+            return None;
+        }
+        let body = &data[header_len..size];
+        data = &data[size..];
+        Some((box_type, body))
+    })
+}
+
+fn find_box<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data).find(|(t, _)| t == box_type).map(|(_, body)| body)
+}
+
+/// 解析 mvhd 中的创建时间（自 1904 年起的 UTC 秒数）
+fn parse_mvhd_creation_time(mvhd: &[u8]) -> Option<DateTime<Utc>> {
+    let version = *mvhd.first()?;
+    let secs = match version {
+        0 => u32::from_be_bytes(mvhd.get(4..8)?.try_into().ok()?) as u64,
+        1 => u64::from_be_bytes(mvhd.get(4..12)?.try_into().ok()?),
+        _ => return None,
+    };
+    mac_time_to_utc(secs)
+}
+
+/// QuickTime 时间戳转换为 UTC 时间；0 表示未设置
+fn mac_time_to_utc(secs: u64) -> Option<DateTime<Utc>> {
+    if secs == 0 {
+        return None;
+    }
+    let unix = i64::try_from(secs).ok()? - MAC_EPOCH_OFFSET;
+    DateTime::from_timestamp(unix, 0)
+}
+
+/// 在 moov/meta 的 keys + ilst 中查找 Apple 元数据的字符串值
+fn find_apple_metadata(moov: &[u8], key: &str) -> Option<String> {
+    let mut meta = find_box(moov, b"meta")?;
+    // QuickTime 的 meta 直接包含子盒子，ISO-BMFF 的 meta 前有 4 字节 version/flags
+    if find_box(meta, b"hdlr").is_none() && find_box(meta, b"keys").is_none() {
+        meta = meta.get(4..)?;
+    }
+    let keys = find_box(meta, b"keys")?;
+    let ilst = find_box(meta, b"ilst")?;
+
+    // keys: version/flags(4) + count(4) + [size(4) + namespace(4) + name]
+    let count = u32::from_be_bytes(keys.get(4..8)?.try_into().ok()?);
+    let mut rest = keys.get(8..)?;
+    let mut index = None;
+    for i in 1..=count {
+        let size = u32::from_be_bytes(rest.get(0..4)?.try_into().ok()?) as usize;
+        if size < 8 || size > rest.len() {
+            return None;
+        }
+        if &rest[8..size] == key.as_bytes() {
+            index = Some(i);
+            break;
+        }
+        rest = &rest[size..];
+    }
+    let index = index?.to_be_bytes();
+
+    // ilst 的子盒子类型为 1 起始的 key 序号
+    let item = boxes(ilst).find(|(t, _)| *t == index).map(|(_, body)| body)?;
+    let data = find_box(item, b"data")?;
+    // data: type(4) + locale(4) + value
+    let value = data.get(8..)?;
+    Some(String::from_utf8_lossy(value).trim_end_matches('\0').to_string())
+}
+
+/// 解析 Apple 创建时间，如 `2023-06-15T10:15:00+0800`
+fn parse_apple_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    APPLE_DATE_FORMATS
+        .iter()
+        .find_map(|fmt| DateTime::parse_from_str(value.trim(), fmt).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_box(box_type: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(box_type);
+        out.extend_from_slice(body);
+        out
+    }
+
+    #[test]
+    fn mac_epoch_is_1904() {
+        let dt = mac_time_to_utc(1).unwrap();
+        assert_eq!(dt.to_rfc3339(), "1904-01-01T00:00:01+00:00");
+        assert_eq!(mac_time_to_utc(0), None);
+    }
+
+    #[test]
+    fn mvhd_version_0_and_1() {
+        // 2023-06-15 10:15:00 UTC
+        let secs: u64 = 1_686_824_100 + MAC_EPOCH_OFFSET as u64;
+
+        let mut v0 = vec![0, 0, 0, 0];
+        v0.extend_from_slice(&(secs as u32).to_be_bytes());
+        v0.extend_from_slice(&[0; 8]);
+        let dt = parse_mvhd_creation_time(&v0).unwrap();
+        assert_eq!(dt.to_rfc3339(), "2023-06-15T10:15:00+00:00");
+
+        let mut v1 = vec![1, 0, 0, 0];
+        v1.extend_from_slice(&secs.to_be_bytes());
+        v1.extend_from_slice(&[0; 16]);
+        assert_eq!(parse_mvhd_creation_time(&v1), Some(dt));
+    }
+
+    #[test]
+    fn mvhd_date_stays_utc_and_oversized_boxes_are_ignored() {
+        let secs: u64 = 1_686_824_100 + MAC_EPOCH_OFFSET as u64;
+        let mut mvhd = vec![0, 0, 0, 0];
+        mvhd.extend_from_slice(&(secs as u32).to_be_bytes());
+        mvhd.extend_from_slice(&[0; 8]);
+        let mut file = make_box(b"ftyp", b"isom\0\0\0\0");
+        file.extend(make_box(b"moov", &make_box(b"mvhd", &mvhd)));
+
+        let dir = std::env::temp_dir().join(format!("porg-video-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.mp4");
+        fs::write(&path, &file).unwrap();
+        let date = extract_creation_date(&path).unwrap().unwrap();
+        assert_eq!(date.offset().unwrap().local_minus_utc(), 0);
+        assert_eq!(date.local().to_string(), "2023-06-15 10:15:00");
+
+        // 64 位盒子大小接近 u64::MAX，跳过时位置会溢出
+        let mut huge = make_box(b"free", b"");
+        huge.extend_from_slice(&[0, 0, 0, 1]);
+        huge.extend_from_slice(b"free");
+        huge.extend_from_slice(&u64::MAX.to_be_bytes());
+        huge.extend_from_slice(&[0; 16]);
+        fs::write(&path, &huge).unwrap();
+        assert_eq!(extract_creation_date(&path).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn apple_creation_date_keeps_local_time() {
+        let key = APPLE_CREATION_DATE_KEY.as_bytes();
+        let mut keys = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        keys.extend_from_slice(&((key.len() + 8) as u32).to_be_bytes());
+        keys.extend_from_slice(b"mdta");
+        keys.extend_from_slice(key);
+
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend_from_slice(b"2023-06-15T18:15:00+0800");
+        let item = make_box(&1u32.to_be_bytes(), &make_box(b"data", &data));
+
+        let mut meta = make_box(b"keys", &keys);
+        meta.extend(make_box(b"ilst", &item));
+        let moov = make_box(b"meta", &meta);
+
+        let value = find_apple_metadata(&moov, APPLE_CREATION_DATE_KEY).unwrap();
+        let dt = parse_apple_date(&value).unwrap();
+        assert_eq!(dt.naive_local().to_string(), "2023-06-15 18:15:00");
+        assert_eq!(dt.offset().to_string(), "+08:00");
+    }
+}
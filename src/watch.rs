@@ -0,0 +1,674 @@
+//! `porg watch`：监视同步文件夹（如 Syncthing），新文件写完后自动整理
+//!
+//! Linux 上用 inotify 接收目录变化，其他系统或 inotify 不可用时定期扫描。每次唤醒都重新扫描目录：
+//! 文件的大小与修改时间在 `settle` 内保持不变才视为写完，目录静默 `settle` 后把写完的文件作为一批整理。
+//! 同一目录下同名的文件还在写入时先留下，使 RAW/JPEG、附属文件与实况照片的视频在同一批中整理。
+//! 已整理的文件记录在输出目录的 `.porg/watch-state` 中，重启后只处理新增或改动过的文件。
+//! 随时结束进程都不会留下不完整的文件：传输先写临时文件再重命名，每批整理后保存记录。
+
+use crate::event::format_duration;
+use crate::filter::Filter;
+use crate::inotify::Inotify;
+use crate::journal;
+use crate::organize::Organizer;
+use crate::transfer;
+use anyhow::{Context, Result};
+use chrono::{Local, TimeDelta};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// 已整理文件的记录（位于输出目录下）
+const STATE_FILE: &str = ".porg/watch-state";
+/// 默认日志文件（位于输出目录下）
+pub const LOG_FILE: &str = ".porg/watch.log";
+/// inotify 不可用时的扫描间隔
+const DEFAULT_POLL: Duration = Duration::from_secs(30);
+/// 没有待整理的文件时也定期重新扫描，防止漏掉事件（如 inotify 队列溢出）
+const RESCAN_INTERVAL: Duration = Duration::from_secs(300);
+/// 两次扫描的最小间隔，大量事件接连到达时合并处理
+const MIN_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+/// 一直有新文件到达时，写完的文件最多等待这么久就整理
+const MAX_DELAY: Duration = Duration::from_secs(300);
+/// 同步软件的版本与标记目录，不整理其中的文件
+const SYNC_DIRS: &[&str] = &[".stversions", ".stfolder"];
+
+/// 监视选项
+pub struct WatchOptions {
+    /// 文件保持不变多久后视为写完，也是合并一批新文件的静默间隔
+    pub settle: Duration,
+    /// 定期扫描的间隔；`None` 时使用 inotify（不可用时退回每 30 秒扫描）
+    pub poll: Option<Duration>,
+    pub log: PathBuf,
+    pub recursive: bool,
+    pub fix_extensions: bool,
+    pub quiet: bool,
+}
+
+/// 文件的大小与修改时间（纳秒）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Snapshot {
+    size: u64,
+    modified: u128,
+}
+
+impl Snapshot {
+    fn of(meta: &fs::Metadata) -> Snapshot {
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        Snapshot {
+            size: meta.len(),
+            modified,
+        }
+    }
+}
+
+/// 尚未写完或等待整理的文件
+struct Pending {
+    snapshot: Snapshot,
+    /// 最近一次变化的时间
+    changed: Instant,
+    /// 首次发现的时间
+    found: Instant,
+}
+
+/// 已整理文件的记录：路径 → 整理时的大小与修改时间
+struct State {
+    path: PathBuf,
+    files: HashMap<PathBuf, Snapshot>,
+}
+
+impl State {
+    /// 读取记录（不存在时为空），忽略无法解析的行
+    fn load(output_dir: &Path) -> State {
+        let path = output_dir.join(STATE_FILE);
+        let text = fs::read_to_string(&path).unwrap_or_default();
+        let files = text
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, '\t');
+                let size = parts.next()?.parse().ok()?;
+                let modified = parts.next()?.parse().ok()?;
+                let file = PathBuf::from(journal::unescape(parts.next()?));
+                Some((file, Snapshot { size, modified }))
+            })
+            .collect();
+        State { path, files }
+    }
+
+    /// 先写临时文件再替换，中断时不会留下不完整的记录；路径按操作日志的方式转义
+    fn save(&self) -> Result<()> {
+        let dir = self.path.parent().context("无效的记录路径")?;
+        fs::create_dir_all(dir).with_context(|| format!("无法创建目录: {}", dir.display()))?;
+        let mut lines: Vec<String> = self
+            .files
+            .iter()
+            .map(|(file, s)| {
+                let file = journal::escape(&file.to_string_lossy());
+                format!("{}\t{}\t{}\n", s.size, s.modified, file)
+            })
+            .collect();
+        lines.sort();
+        let tmp = transfer::temp_path(&self.path);
+        fs::write(&tmp, lines.concat())
+            .and_then(|()| fs::rename(&tmp, &self.path))
+            .with_context(|| format!("无法写入监视记录: {}", self.path.display()))
+    }
+
+    /// 文件是否已按当前内容整理过
+    fn is_done(&self, file: &Path, snapshot: Snapshot) -> bool {
+        self.files.get(file) == Some(&snapshot)
+    }
+}
+
+/// 决定哪些文件已写完、何时整理为一批；时间由调用方传入
+struct Batcher {
+    settle: Duration,
+    pending: HashMap<PathBuf, Pending>,
+    /// 整理失败的文件内容不变时不再重试，改动后或重启后再试
+    failed: HashMap<PathBuf, Snapshot>,
+    /// 同步软件正在接收的文件
+    incoming: HashSet<PathBuf>,
+    /// 最近一次有文件出现或变化的时间
+    last_change: Instant,
+}
+
+impl Batcher {
+    fn new(settle: Duration, now: Instant) -> Batcher {
+        Batcher {
+            settle,
+            pending: HashMap::new(),
+            failed: HashMap::new(),
+            incoming: HashSet::new(),
+            last_change: now,
+        }
+    }
+
+    /// 用一次扫描的结果更新待整理的文件，跳过已整理过的与内容未变的失败文件
+    fn update(&mut self, found: &Scan, state: &State, now: Instant) {
+        let files = &found.files;
+        self.failed.retain(|file, snapshot| files.get(file) == Some(snapshot));
+        self.pending.retain(|file, _| files.contains_key(file));
+        self.incoming = found.incoming.clone();
+        for (file, &snapshot) in files {
+            if state.is_done(file, snapshot) || self.failed.get(file) == Some(&snapshot) {
+                continue;
+            }
+            match self.pending.get_mut(file) {
+                Some(p) if p.snapshot == snapshot => {}
+                Some(p) => {
+                    p.snapshot = snapshot;
+                    p.changed = now;
+                    self.last_change = now;
+                }
+                None => {
+                    self.pending.insert(
+                        file.clone(),
+                        Pending {
+                            snapshot,
+                            changed: now,
+                            found: now,
+                        },
+                    );
+                    self.last_change = now;
+                }
+            }
+        }
+    }
+
+    /// 可以整理的一批文件
+    ///
+    /// 大小与修改时间 `settle` 内不变的文件视为写完。目录静默 `settle` 后，或有写完的文件
+    /// 已等待 `MAX_DELAY` 时，返回写完的文件；同一目录下同名的文件还在写入或接收时先留下，
+    /// 等待超过 `MAX_DELAY` 的除外。
+    fn ready(&self, now: Instant) -> Vec<PathBuf> {
+        let written = |p: &Pending| now.duration_since(p.changed) >= self.settle;
+        let overdue = |p: &Pending| now.duration_since(p.found) >= MAX_DELAY;
+        let busy: HashSet<(&Path, &str)> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| !written(p))
+            .map(|(file, _)| file)
+            .chain(&self.incoming)
+            .filter_map(|file| stem_key(file))
+            .collect();
+        let ready: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(file, p)| {
+                written(p) && (overdue(p) || stem_key(file).is_none_or(|key| !busy.contains(&key)))
+            })
+            .map(|(file, _)| file.clone())
+            .collect();
+        let quiet = now.duration_since(self.last_change) >= self.settle;
+        if quiet || ready.iter().any(|file| overdue(&self.pending[file])) {
+            ready
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// 记录一批的结果：`retry` 中的文件改动后再试，其余记为已整理
+    fn finish(&mut self, batch: Vec<PathBuf>, retry: &HashSet<PathBuf>, state: &mut State) {
+        for file in batch {
+            if let Some(p) = self.pending.remove(&file) {
+                if retry.contains(&file) {
+                    self.failed.insert(file, p.snapshot);
+                } else {
+                    state.files.insert(file, p.snapshot);
+                }
+            }
+        }
+    }
+}
+
+/// 判断同名文件所用的键：所在目录与文件名第一个 `.` 之前的部分
+/// （`IMG_1.CR2`、`IMG_1.JPG`、`IMG_1.JPG.xmp` 与 `IMG_1.MOV` 相同）
+fn stem_key(path: &Path) -> Option<(&Path, &str)> {
+    let name = path.file_name()?.to_str()?;
+    Some((path.parent()?, name.split('.').next()?))
+}
+
+/// 追加写入的日志文件
+struct Log {
+    file: fs::File,
+}
+
+impl Log {
+    fn open(path: &Path) -> Result<Log> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("无法创建目录: {}", dir.display()))?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("无法打开日志文件: {}", path.display()))?;
+        Ok(Log { file })
+    }
+
+    /// 写入一行带时间的日志；写入失败不影响整理
+    fn write(&mut self, message: &str) {
+        let time = Local::now().format("%Y-%m-%d %H:%M:%S");
+        let _ = writeln!(self.file, "[{}] {}", time, message);
+    }
+}
+
+/// 监视 `dir`，把写完的新文件交给 `organizer` 整理，直到进程被结束
+pub fn watch(
+    dir: &Path,
+    mut filter: Filter,
+    organizer: &mut Organizer,
+    options: &WatchOptions,
+) -> Result<()> {
+    for name in SYNC_DIRS {
+        filter.skip_dir(dir.join(name));
+    }
+    let mut log = Log::open(&options.log)?;
+    let mut state = State::load(organizer.output_dir());
+    let mut watcher = Watcher::new(options.poll);
+
+    let started = format!(
+        "开始监视 {}（{}），输出到 {}",
+        dir.display(),
+        watcher.describe(),
+        organizer.output_dir().display()
+    );
+    log.write(&started);
+    if !options.quiet {
+        println!("👀 {}", started);
+        println!("📝 日志: {}（Ctrl-C 停止）\n", options.log.display());
+    }
+
+    let mut batcher = Batcher::new(options.settle, Instant::now());
+    let mut last_scan: Option<Instant> = None;
+    loop {
+        if let Some(elapsed) = last_scan.map(|t| t.elapsed()) {
+            thread::sleep(MIN_SCAN_INTERVAL.saturating_sub(elapsed));
+        }
+        let found = scan(dir, options.recursive, &mut filter);
+        last_scan = Some(Instant::now());
+        if let Err(e) = watcher.add_dirs(&found.dirs) {
+            let message = format!("无法继续使用 inotify（{}），改为每 {} 扫描", e, describe(DEFAULT_POLL));
+            eprintln!("⚠️  {}", message);
+            log.write(&message);
+            watcher = Watcher::Poll(DEFAULT_POLL);
+        }
+
+        // 移动模式下源文件整理后即被删除，不再需要记录
+        let before = state.files.len();
+        state.files.retain(|file, _| found.files.contains_key(file));
+        let mut dirty = state.files.len() != before;
+
+        let now = Instant::now();
+        batcher.update(&found, &state, now);
+        let batch = batcher.ready(now);
+        if !batch.is_empty() {
+            let retry = organize_batch(&batch, &filter, organizer, options, &mut log);
+            batcher.finish(batch, &retry, &mut state);
+            dirty = true;
+        }
+        if dirty {
+            if let Err(e) = state.save() {
+                eprintln!("⚠️  {:#}", e);
+                log.write(&format!("{:#}", e));
+            }
+        }
+
+        // 有待整理的文件时按 settle 的节奏复查大小，否则等待目录变化
+        let timeout = if batcher.pending.is_empty() {
+            RESCAN_INTERVAL
+        } else {
+            (options.settle / 4).max(MIN_SCAN_INTERVAL)
+        };
+        if let Err(e) = watcher.wait(timeout) {
+            let message = format!("等待目录变化失败（{}），改为每 {} 扫描", e, describe(DEFAULT_POLL));
+            eprintln!("⚠️  {}", message);
+            log.write(&message);
+            watcher = Watcher::Poll(DEFAULT_POLL);
+        }
+    }
+}
+
+/// 整理一批写完的文件，返回需要稍后重试的文件（所在组有文件整理失败）
+fn organize_batch(
+    files: &[PathBuf],
+    filter: &Filter,
+    organizer: &mut Organizer,
+    options: &WatchOptions,
+    log: &mut Log,
+) -> HashSet<PathBuf> {
+    let mut groups = crate::group_files(files.to_vec(), filter, options.fix_extensions);
+    if groups.is_empty() {
+        return HashSet::new();
+    }
+    crate::pair_live_photos(&mut groups, options.quiet);
+    let count: usize = groups.iter().map(|g| g.items.len()).sum();
+    if !options.quiet {
+        println!("📸 {} 个新文件写入完成\n", count);
+    }
+
+    organizer.next_run();
+    organizer.run(&groups);
+
+    let stats = &organizer.stats;
+    let mut summary = format!(
+        "整理 {} 个文件: 已分类 {}  未分类 {}  重复 {}  跳过 {}  错误 {}",
+        count, stats.organized, stats.unsorted, stats.duplicates, stats.skipped, stats.errors
+    );
+    if !organizer.journal.is_empty() {
+        summary = format!("{}（运行 ID: {}）", summary, organizer.journal.run_id());
+    }
+    log.write(&summary);
+    for (file, error) in &organizer.failures {
+        log.write(&format!("处理失败: {} — {}", file.display(), error));
+    }
+    for sidecar in &stats.skipped_sidecars {
+        log.write(&format!("附属文件未处理（所属文件重复）: {}", sidecar.display()));
+    }
+    if !options.quiet {
+        println!("\n✅ {}\n", summary);
+    }
+
+    let failed: HashSet<&Path> = organizer.failures.iter().map(|(f, _)| f.as_path()).collect();
+    let mut retry = HashSet::new();
+    for group in &groups {
+        let paths: Vec<&PathBuf> = group
+            .items
+            .iter()
+            .flat_map(|item| {
+                std::iter::once(&item.path).chain(item.sidecars.iter().map(|sc| &sc.path))
+            })
+            .collect();
+        if paths.iter().any(|path| failed.contains(path.as_path())) {
+            retry.extend(paths.into_iter().cloned());
+        }
+    }
+    retry
+}
+
+/// 一次扫描的结果
+struct Scan {
+    /// 筛选条件允许的文件（不含同步软件与 porg 的临时文件）及其快照
+    files: HashMap<PathBuf, Snapshot>,
+    /// 同步软件正在接收的文件（接收完成后的路径）
+    incoming: HashSet<PathBuf>,
+    dirs: Vec<PathBuf>,
+}
+
+/// 扫描目录中的文件与所有子目录
+fn scan(dir: &Path, recursive: bool, filter: &mut Filter) -> Scan {
+    let walker = if recursive {
+        WalkDir::new(dir)
+    } else {
+        WalkDir::new(dir).max_depth(1)
+    };
+    let mut files = HashMap::new();
+    let mut incoming = HashSet::new();
+    let mut dirs = Vec::new();
+    let entries = walker
+        .into_iter()
+        .filter_entry(|e| filter.allows(e.path(), e.file_type().is_dir()))
+        .filter_map(|e| e.ok());
+    for entry in entries {
+        if entry.file_type().is_dir() {
+            dirs.push(entry.into_path());
+        } else if is_partial(entry.path()) {
+            let name = entry.file_name().to_string_lossy();
+            if let Some(target) = syncthing_target(&name) {
+                incoming.insert(entry.path().with_file_name(target));
+            }
+        } else {
+            match fs::metadata(entry.path()) {
+                Ok(meta) if meta.is_file() => {
+                    files.insert(entry.into_path(), Snapshot::of(&meta));
+                }
+                _ => {}
+            }
+        }
+    }
+    Scan {
+        files,
+        incoming,
+        dirs,
+    }
+}
+
+/// 同步或传输中的临时文件：Syncthing 的 `.syncthing.*.tmp`、`~syncthing~*.tmp` 与 porg 的临时文件
+fn is_partial(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    syncthing_target(&name).is_some() || transfer::is_temp(path)
+}
+
+/// Syncthing 临时文件接收完成后的文件名
+fn syncthing_target(name: &str) -> Option<&str> {
+    name.strip_prefix(".syncthing.")
+        .or_else(|| name.strip_prefix("~syncthing~"))?
+        .strip_suffix(".tmp")
+}
+
+/// 以 `30s` 形式显示时长
+fn describe(d: Duration) -> String {
+    TimeDelta::from_std(d).map_or_else(|_| format!("{:?}", d), format_duration)
+}
+
+/// 等待目录变化的方式
+enum Watcher {
+    Inotify(Inotify),
+    /// 每隔给定时间扫描一次
+    Poll(Duration),
+}
+
+impl Watcher {
+    fn new(poll: Option<Duration>) -> Watcher {
+        if let Some(interval) = poll {
+            return Watcher::Poll(interval);
+        }
+        match Inotify::new() {
+            Ok(inotify) => Watcher::Inotify(inotify),
+            Err(e) => {
+                eprintln!("⚠️  无法使用 inotify（{}），改为每 {} 扫描", e, describe(DEFAULT_POLL));
+                Watcher::Poll(DEFAULT_POLL)
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Watcher::Inotify(_) => "inotify".to_string(),
+            Watcher::Poll(interval) => format!("每 {} 扫描", describe(*interval)),
+        }
+    }
+
+    /// 监视扫描到的目录（重复添加没有副作用）
+    fn add_dirs(&mut self, dirs: &[PathBuf]) -> io::Result<()> {
+        match self {
+            Watcher::Inotify(inotify) => dirs.iter().try_for_each(|dir| inotify.add(dir)),
+            Watcher::Poll(_) => Ok(()),
+        }
+    }
+
+    /// 等待目录变化，最多等待 `timeout`
+    fn wait(&mut self, timeout: Duration) -> io::Result<()> {
+        match self {
+            Watcher::Inotify(inotify) => inotify.wait(timeout),
+            Watcher::Poll(interval) => {
+                thread::sleep(timeout.min(*interval));
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_round_trip_and_partial_files() {
+        let dir = std::env::temp_dir().join(format!("porg-watch-{}", std::process::id()));
+        let snapshot = Snapshot {
+            size: 42,
+            modified: 1_686_824_100_000_000_123,
+        };
+        let file = dir.join("相册/IMG 1.jpg");
+        let odd = dir.join("a\tb\nc\\d.jpg");
+        let mut state = State::load(&dir);
+        assert!(state.files.is_empty());
+        state.files.insert(file.clone(), snapshot);
+        state.files.insert(odd.clone(), snapshot);
+        state.save().unwrap();
+
+        let state = State::load(&dir);
+        assert_eq!(state.files.len(), 2);
+        assert!(state.is_done(&file, snapshot));
+        assert!(state.is_done(&odd, snapshot));
+        assert!(!state.is_done(&file, Snapshot { size: 43, ..snapshot }));
+        assert!(!transfer::temp_path(&state.path).exists());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(is_partial(Path::new("/sync/.syncthing.IMG_1.jpg.tmp")));
+        assert!(is_partial(Path::new("/sync/~syncthing~IMG_1.jpg.tmp")));
+        assert!(!is_partial(Path::new("/sync/IMG_1.jpg")));
+        assert_eq!(syncthing_target(".syncthing.IMG_1.MOV.tmp"), Some("IMG_1.MOV"));
+    }
+
+    const SETTLE: Duration = Duration::from_secs(10);
+
+    fn snapshot(size: u64) -> Snapshot {
+        Snapshot { size, modified: 1 }
+    }
+
+    fn found(files: &[(&str, u64)], incoming: &[&str]) -> Scan {
+        Scan {
+            files: files.iter().map(|&(f, size)| (PathBuf::from(f), snapshot(size))).collect(),
+            incoming: incoming.iter().map(PathBuf::from).collect(),
+            dirs: Vec::new(),
+        }
+    }
+
+    fn empty_state() -> State {
+        State {
+            path: PathBuf::from(STATE_FILE),
+            files: HashMap::new(),
+        }
+    }
+
+    fn sorted(mut files: Vec<PathBuf>) -> Vec<PathBuf> {
+        files.sort();
+        files
+    }
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn waits_until_file_stops_growing() {
+        let (t0, state) = (Instant::now(), empty_state());
+        let mut batcher = Batcher::new(SETTLE, t0);
+        for (at, size) in [(0, 100), (5, 200), (12, 300)] {
+            batcher.update(&found(&[("/s/a.jpg", size)], &[]), &state, t0 + secs(at));
+            assert!(batcher.ready(t0 + secs(at)).is_empty());
+        }
+        batcher.update(&found(&[("/s/a.jpg", 300)], &[]), &state, t0 + secs(21));
+        assert!(batcher.ready(t0 + secs(21)).is_empty());
+        assert_eq!(batcher.ready(t0 + secs(22)), [PathBuf::from("/s/a.jpg")]);
+    }
+
+    #[test]
+    fn burst_is_organized_after_quiet() {
+        let (t0, state) = (Instant::now(), empty_state());
+        let mut batcher = Batcher::new(SETTLE, t0);
+        batcher.update(&found(&[("/s/a.jpg", 1)], &[]), &state, t0);
+        let burst = found(&[("/s/a.jpg", 1), ("/s/b.jpg", 1)], &[]);
+        batcher.update(&burst, &state, t0 + secs(6));
+        // a 已写完，但 b 刚到达，目录尚未静默
+        assert!(batcher.ready(t0 + secs(12)).is_empty());
+        batcher.update(&burst, &state, t0 + secs(16));
+        let batch = batcher.ready(t0 + secs(16));
+        assert_eq!(sorted(batch), [PathBuf::from("/s/a.jpg"), PathBuf::from("/s/b.jpg")]);
+    }
+
+    #[test]
+    fn overdue_files_do_not_wait_for_quiet() {
+        let (t0, state) = (Instant::now(), empty_state());
+        let mut batcher = Batcher::new(SETTLE, t0);
+        let mut files = vec![("/s/first.jpg".to_string(), 1)];
+        let mut at = Duration::ZERO;
+        // 每 5 秒到达一个新文件，目录一直不静默
+        while at < MAX_DELAY {
+            let names: Vec<(&str, u64)> = files.iter().map(|(f, s)| (f.as_str(), *s)).collect();
+            batcher.update(&found(&names, &[]), &state, t0 + at);
+            assert!(batcher.ready(t0 + at).is_empty(), "{:?}", at);
+            at += secs(5);
+            files.push((format!("/s/{}.jpg", at.as_secs()), 1));
+        }
+        let names: Vec<(&str, u64)> = files.iter().map(|(f, s)| (f.as_str(), *s)).collect();
+        batcher.update(&found(&names, &[]), &state, t0 + at);
+        let batch = batcher.ready(t0 + at);
+        assert!(batch.contains(&PathBuf::from("/s/first.jpg")));
+        assert!(!batch.contains(&PathBuf::from(format!("/s/{}.jpg", at.as_secs()))));
+    }
+
+    #[test]
+    fn failed_file_is_retried_after_change() {
+        let (t0, mut state) = (Instant::now(), empty_state());
+        let mut batcher = Batcher::new(SETTLE, t0);
+        batcher.update(&found(&[("/s/a.jpg", 1)], &[]), &state, t0);
+        let batch = batcher.ready(t0 + SETTLE);
+        let retry: HashSet<PathBuf> = batch.iter().cloned().collect();
+        batcher.finish(batch, &retry, &mut state);
+        assert!(state.files.is_empty());
+
+        batcher.update(&found(&[("/s/a.jpg", 1)], &[]), &state, t0 + secs(60));
+        assert!(batcher.pending.is_empty());
+        assert!(batcher.ready(t0 + secs(120)).is_empty());
+
+        batcher.update(&found(&[("/s/a.jpg", 2)], &[]), &state, t0 + secs(130));
+        let batch = batcher.ready(t0 + secs(140));
+        assert_eq!(batch, [PathBuf::from("/s/a.jpg")]);
+        batcher.finish(batch, &HashSet::new(), &mut state);
+        assert!(state.is_done(Path::new("/s/a.jpg"), snapshot(2)));
+        batcher.update(&found(&[("/s/a.jpg", 2)], &[]), &state, t0 + secs(150));
+        assert!(batcher.pending.is_empty());
+    }
+
+    #[test]
+    fn holds_back_files_whose_companions_are_pending() {
+        let (t0, state) = (Instant::now(), empty_state());
+        let mut batcher = Batcher::new(SETTLE, t0);
+        // 照片已写完，同名的视频仍在由 Syncthing 接收
+        let receiving = found(&[("/s/IMG_1.JPG", 1), ("/s/IMG_2.JPG", 1)], &["/s/IMG_1.MOV"]);
+        batcher.update(&receiving, &state, t0);
+        assert_eq!(batcher.ready(t0 + SETTLE), [PathBuf::from("/s/IMG_2.JPG")]);
+        let done = found(&[("/s/IMG_1.JPG", 1), ("/s/IMG_1.MOV", 1)], &[]);
+        batcher.update(&done, &state, t0 + SETTLE);
+        let batch = sorted(batcher.ready(t0 + SETTLE * 2));
+        assert_eq!(batch, [PathBuf::from("/s/IMG_1.JPG"), PathBuf::from("/s/IMG_1.MOV")]);
+
+        // 有文件超时而整理一批时，RAW 仍在写入的 JPEG 与附属文件继续等待，其他目录不受影响
+        let mut batcher = Batcher::new(SETTLE, t0);
+        batcher.update(&found(&[("/s/old.jpg", 1)], &[]), &state, t0);
+        let names =
+            ["/s/old.jpg", "/s/IMG_3.CR2", "/s/IMG_3.JPG", "/s/IMG_3.CR2.xmp", "/t/IMG_3.CR2"];
+        let raw = |size| found(&names.map(|f| (f, if f == names[1] { size } else { 1 })), &[]);
+        batcher.update(&raw(1), &state, t0 + MAX_DELAY - secs(20));
+        batcher.update(&raw(2), &state, t0 + MAX_DELAY - secs(5));
+        let batch = sorted(batcher.ready(t0 + MAX_DELAY));
+        assert_eq!(batch, [PathBuf::from("/s/old.jpg"), PathBuf::from("/t/IMG_3.CR2")]);
+
+        // 等待超过 MAX_DELAY 的文件不再等待同名文件
+        let mut batcher = Batcher::new(SETTLE, t0);
+        batcher.update(&receiving, &state, t0);
+        let batch = sorted(batcher.ready(t0 + MAX_DELAY));
+        assert_eq!(batch, [PathBuf::from("/s/IMG_1.JPG"), PathBuf::from("/s/IMG_2.JPG")]);
+    }
+}
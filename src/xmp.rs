@@ -0,0 +1,148 @@
+//! 写入 XMP 附属文件中的拍摄日期（无法安全改写 EXIF 的格式使用）
+
+use anyhow::{Context, Result};
+use chrono::{FixedOffset, NaiveDateTime};
+use std::fs;
+use std::path::Path;
+
+const EXIF_NS: (&str, &str) = ("exif", "http://ns.adobe.com/exif/1.0/");
+const PHOTOSHOP_NS: (&str, &str) = ("photoshop", "http://ns.adobe.com/photoshop/1.0/");
+
+/// 在 XMP 附属文件中写入 `exif:DateTimeOriginal` 与 `photoshop:DateCreated`；
+/// 文件不存在时新建，已存在时只改这两个属性
+pub fn write_date(
+    path: &Path,
+    time: NaiveDateTime,
+    offset: Option<FixedOffset>,
+    dry_run: bool,
+) -> Result<()> {
+    let value = match offset.and_then(|offset| time.and_local_timezone(offset).single()) {
+        Some(dt) => dt.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+        None => time.format("%Y-%m-%dT%H:%M:%S").to_string(),
+    };
+
+    let mut xmp = if path.exists() {
+        fs::read_to_string(path).with_context(|| format!("无法读取: {}", path.display()))?
+    } else {
+        empty_packet()
+    };
+    set_property(&mut xmp, EXIF_NS, "DateTimeOriginal", &value)
+        .with_context(|| format!("无法识别的 XMP 文件: {}", path.display()))?;
+    set_property(&mut xmp, PHOTOSHOP_NS, "DateCreated", &value)
+        .with_context(|| format!("无法识别的 XMP 文件: {}", path.display()))?;
+
+    if !dry_run {
+        fs::write(path, xmp).with_context(|| format!("无法写入: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn empty_packet() -> String {
+    concat!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+        " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+        "  <rdf:Description rdf:about=\"\"/>\n",
+        " </rdf:RDF>\n",
+        "</x:xmpmeta>\n",
+        "<?xpacket end=\"w\"?>\n",
+    )
+    .to_string()
+}
+
+/// 设置属性值：已有属性或元素形式时原地替换，否则加到第一个 rdf:Description 上
+fn set_property(
+    xmp: &mut String,
+    (prefix, uri): (&str, &str),
+    name: &str,
+    value: &str,
+) -> Result<()> {
+    let qualified = format!("{}:{}", prefix, name);
+
+    // 属性形式: exif:DateTimeOriginal="..."
+    let attr = format!("{}=\"", qualified);
+    if let Some(pos) = xmp.find(&attr) {
+        let start = pos + attr.len();
+        let end = start + xmp[start..].find('"').context("属性值缺少结尾引号")?;
+        xmp.replace_range(start..end, value);
+        return Ok(());
+    }
+
+    // 元素形式: <exif:DateTimeOriginal>...</exif:DateTimeOriginal>
+    let open = format!("<{}>", qualified);
+    let close = format!("</{}>", qualified);
+    if let Some(pos) = xmp.find(&open) {
+        let start = pos + open.len();
+        let end = start + xmp[start..].find(&close).context("元素缺少结束标签")?;
+        xmp.replace_range(start..end, value);
+        return Ok(());
+    }
+
+    let tag = "<rdf:Description";
+    let pos = xmp.find(tag).context("缺少 rdf:Description")? + tag.len();
+    // 命名空间须在该元素或其祖先上声明（它们都出现在标签结束之前）
+    let tag_end = pos + xmp[pos..].find('>').context("rdf:Description 标签未结束")?;
+    let mut insert = String::new();
+    if !xmp[..tag_end].contains(&format!("xmlns:{}=", prefix)) {
+        insert.push_str(&format!(" xmlns:{}=\"{}\"", prefix, uri));
+    }
+    insert.push_str(&format!(" {}=\"{}\"", qualified, value));
+    xmp.insert_str(pos, &insert);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time() -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("2023-06-15 10:15:00", "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn creates_and_updates_sidecars() {
+        let dir = std::env::temp_dir().join(format!("porg-xmp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.xmp");
+
+        write_date(&path, time(), FixedOffset::west_opt(5 * 3600), false).unwrap();
+        let xmp = fs::read_to_string(&path).unwrap();
+        assert!(xmp.contains(r#"xmlns:exif="http://ns.adobe.com/exif/1.0/""#));
+        assert!(xmp.contains(r#"exif:DateTimeOriginal="2023-06-15T10:15:00-05:00""#));
+        assert!(xmp.contains(r#"photoshop:DateCreated="2023-06-15T10:15:00-05:00""#));
+
+        // 已有属性原地替换，不重复声明命名空间
+        write_date(&path, time(), None, false).unwrap();
+        let xmp = fs::read_to_string(&path).unwrap();
+        assert_eq!(xmp.matches("exif:DateTimeOriginal=").count(), 1);
+        assert_eq!(xmp.matches("xmlns:exif=").count(), 1);
+        assert!(xmp.contains(r#"exif:DateTimeOriginal="2023-06-15T10:15:00""#));
+
+        // 预览模式不写文件
+        write_date(&dir.join("b.xmp"), time(), None, true).unwrap();
+        assert!(!dir.join("b.xmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_element_form() {
+        let mut xmp = concat!(
+            r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+            r#"<rdf:Description xmlns:exif="http://ns.adobe.com/exif/1.0/">"#,
+            "<exif:DateTimeOriginal>2000-01-01T00:00:00</exif:DateTimeOriginal>",
+            "</rdf:Description></rdf:RDF>",
+        )
+        .to_string();
+        set_property(&mut xmp, EXIF_NS, "DateTimeOriginal", "2023-06-15T10:15:00").unwrap();
+        assert!(xmp.contains("<exif:DateTimeOriginal>2023-06-15T10:15:00</exif:DateTimeOriginal>"));
+        set_property(&mut xmp, PHOTOSHOP_NS, "DateCreated", "2023-06-15T10:15:00").unwrap();
+        let expected = concat!(
+            r#" xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/""#,
+            r#" photoshop:DateCreated="2023-06-15T10:15:00""#,
+        );
+        assert!(xmp.contains(expected), "{}", xmp);
+
+        let mut broken = "<x:xmpmeta/>".to_string();
+        assert!(set_property(&mut broken, EXIF_NS, "DateTimeOriginal", "x").is_err());
+    }
+}